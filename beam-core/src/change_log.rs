@@ -0,0 +1,279 @@
+use crate::checksum::{HashAlgorithm, Hasher};
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+const CHANGE_LOG_FILE: &str = "change_log.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Replaced,
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryChange {
+    pub name: String,
+    pub kind: ChangeKind,
+    // Size and SHA-256 of the new contents; None for removals.
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+}
+
+impl EntryChange {
+    // `existed` tells whether the target already had the file before the patch.
+    pub fn new(name: &str, data: Option<&[u8]>, existed: bool) -> Self {
+        let kind = match (data, existed) {
+            (None, _) => ChangeKind::Removed,
+            (Some(_), true) => ChangeKind::Replaced,
+            (Some(_), false) => ChangeKind::Added,
+        };
+        
+        EntryChange {
+            name: name.to_string(),
+            kind,
+            size: data.map(|data| data.len() as u64),
+            sha256: data.map(|data| {
+                let mut hasher = Hasher::new(HashAlgorithm::Sha256);
+                hasher.update(data);
+                hasher.finalize()
+            }),
+        }
+    }
+}
+
+// The entries one applied patch touched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatchChanges {
+    pub filename: String,
+    pub checksum: Option<String>,
+    pub target: String,
+    pub applied_at: u64,
+    pub changes: Vec<EntryChange>,
+}
+
+impl PatchChanges {
+    pub fn new(filename: String, checksum: Option<String>, target: String, changes: Vec<EntryChange>) -> Self {
+        PatchChanges {
+            filename,
+            checksum,
+            target,
+            applied_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            changes,
+        }
+    }
+}
+
+// Filters for ChangeLog::query; every field left empty matches everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChangeLogQuery {
+    // Patch filename, matched exactly.
+    pub patch: Option<String>,
+    // Part of an entry name, ignoring case and slash direction. Matching
+    // records only keep the changes to those entries.
+    pub entry: Option<String>,
+    // Unix timestamp; only patches applied at or after it.
+    pub since: Option<u64>,
+}
+
+// History of the entries each applied patch changed, one JSON record per
+// line in .patch_cache. It only grows; reset_cache leaves it alone so support
+// can still trace older patches.
+pub struct ChangeLog {
+    path: PathBuf,
+}
+
+impl ChangeLog {
+    pub fn new<P: AsRef<Path>>(cache_dir: P) -> Self {
+        ChangeLog {
+            path: cache_dir.as_ref().join(CHANGE_LOG_FILE),
+        }
+    }
+    
+    // Oldest first. A line cut short by a crash is skipped.
+    pub fn load(&self) -> Result<Vec<PatchChanges>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        
+        let content = std::fs::read_to_string(&self.path)?;
+        let mut records = Vec::new();
+        
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(record) => records.push(record),
+                Err(e) => warn!("Skipping unreadable change log line: {}", e),
+            }
+        }
+        
+        Ok(records)
+    }
+    
+    pub fn append(&self, records: &[PatchChanges]) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        
+        let mut lines = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut lines, record)?;
+            lines.push(b'\n');
+        }
+        
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        
+        // Start on a fresh line if a crash cut the last record short.
+        if file.metadata()?.len() > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                lines.insert(0, b'\n');
+            }
+        }
+        file.write_all(&lines)?;
+        file.sync_all()?;
+        Ok(())
+    }
+    
+    // Appends the records not logged yet, for recovery replaying a journal
+    // entry whose records may already have been written.
+    pub fn append_missing(&self, records: &[PatchChanges]) -> Result<()> {
+        let logged = self.load()?;
+        let missing: Vec<PatchChanges> = records
+            .iter()
+            .filter(|record| !logged.iter().any(|l| {
+                l.filename == record.filename && l.applied_at == record.applied_at
+            }))
+            .cloned()
+            .collect();
+        
+        self.append(&missing)
+    }
+    
+    // Newest first.
+    pub fn query(&self, query: &ChangeLogQuery) -> Result<Vec<PatchChanges>> {
+        let entry = query.entry.as_deref().map(normalize_name);
+        let mut matches = Vec::new();
+        
+        for mut record in self.load()?.into_iter().rev() {
+            if query.patch.as_ref().is_some_and(|patch| *patch != record.filename) {
+                continue;
+            }
+            if query.since.is_some_and(|since| record.applied_at < since) {
+                continue;
+            }
+            if let Some(entry) = &entry {
+                record.changes.retain(|change| normalize_name(&change.name).contains(entry.as_str()));
+                if record.changes.is_empty() {
+                    continue;
+                }
+            }
+            matches.push(record);
+        }
+        
+        Ok(matches)
+    }
+}
+
+fn normalize_name(name: &str) -> String {
+    name.replace('/', "\\").to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn record(filename: &str, applied_at: u64, names: &[&str]) -> PatchChanges {
+        PatchChanges {
+            filename: filename.to_string(),
+            checksum: None,
+            target: "data.grf".to_string(),
+            applied_at,
+            changes: names.iter().map(|name| EntryChange::new(name, Some(b"data"), false)).collect(),
+        }
+    }
+    
+    #[test]
+    fn append_missing_skips_logged_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = ChangeLog::new(dir.path());
+        let first = record("a.thor", 100, &["data\\a.txt"]);
+        let second = record("b.thor", 200, &["data\\b.txt"]);
+        
+        log.append(std::slice::from_ref(&first)).unwrap();
+        log.append_missing(&[first.clone(), second.clone()]).unwrap();
+        log.append_missing(&[first.clone(), second.clone()]).unwrap();
+        
+        assert_eq!(log.load().unwrap(), vec![first, second]);
+    }
+    
+    #[test]
+    fn query_filters_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = ChangeLog::new(dir.path());
+        log.append(&[
+            record("a.thor", 100, &["data\\sprite\\Poring.spr", "data\\a.txt"]),
+            record("b.thor", 200, &["data\\b.txt"]),
+            record("c.thor", 300, &["data/sprite/poring.act"]),
+        ])
+        .unwrap();
+        
+        let all = log.query(&ChangeLogQuery::default()).unwrap();
+        let names: Vec<&str> = all.iter().map(|r| r.filename.as_str()).collect();
+        assert_eq!(names, vec!["c.thor", "b.thor", "a.thor"]);
+        
+        let by_patch = log.query(&ChangeLogQuery { patch: Some("b.thor".to_string()), ..Default::default() }).unwrap();
+        assert_eq!(by_patch.len(), 1);
+        assert_eq!(by_patch[0].filename, "b.thor");
+        
+        let since = log.query(&ChangeLogQuery { since: Some(200), ..Default::default() }).unwrap();
+        assert_eq!(since.len(), 2);
+        
+        // Entry matches ignore case and slash direction, and drop the other changes.
+        let by_entry = log.query(&ChangeLogQuery { entry: Some("SPRITE/poring".to_string()), ..Default::default() }).unwrap();
+        assert_eq!(by_entry.len(), 2);
+        assert_eq!(by_entry[1].changes.len(), 1);
+        assert_eq!(by_entry[1].changes[0].name, "data\\sprite\\Poring.spr");
+    }
+    
+    #[test]
+    fn load_skips_truncated_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = ChangeLog::new(dir.path());
+        log.append(&[record("a.thor", 100, &["data\\a.txt"])]).unwrap();
+        
+        let mut file = std::fs::OpenOptions::new().append(true).open(dir.path().join(CHANGE_LOG_FILE)).unwrap();
+        file.write_all(b"{\"filename\":\"b.th").unwrap();
+        
+        assert_eq!(log.load().unwrap().len(), 1);
+        
+        log.append(&[record("c.thor", 300, &["data\\c.txt"])]).unwrap();
+        let names: Vec<String> = log.load().unwrap().into_iter().map(|r| r.filename).collect();
+        assert_eq!(names, vec!["a.thor", "c.thor"]);
+    }
+    
+    #[test]
+    fn entry_change_kind() {
+        assert_eq!(EntryChange::new("a", Some(b"x"), false).kind, ChangeKind::Added);
+        assert_eq!(EntryChange::new("a", Some(b"x"), true).kind, ChangeKind::Replaced);
+        
+        let removed = EntryChange::new("a", None, true);
+        assert_eq!(removed.kind, ChangeKind::Removed);
+        assert_eq!(removed.size, None);
+        assert_eq!(removed.sha256, None);
+    }
+}
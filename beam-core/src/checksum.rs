@@ -0,0 +1,142 @@
+use crate::{Error, Result};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+// Checksum algorithms a patch list may name. SHA-256 is the default; the
+// others exist so checksums published by older RO patch servers can be used
+// as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Md5,
+    Crc32,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    // None selects SHA-256.
+    pub fn parse(name: Option<&str>) -> Result<Self> {
+        let Some(name) = name.map(str::trim) else {
+            return Ok(HashAlgorithm::Sha256);
+        };
+        
+        match name.to_ascii_lowercase().replace('-', "").as_str() {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "md5" => Ok(HashAlgorithm::Md5),
+            "crc32" => Ok(HashAlgorithm::Crc32),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            _ => Err(Error::InvalidConfig(format!("Unsupported hash algorithm: {}", name))),
+        }
+    }
+    
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Crc32 => "crc32",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+// Incremental hasher for any supported algorithm, fed as data arrives.
+pub enum Hasher {
+    Sha256(Sha256),
+    Md5(md5::Context),
+    Crc32(crc32fast::Hasher),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Md5 => Hasher::Md5(md5::Context::new()),
+            HashAlgorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+    
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Md5(context) => context.consume(data),
+            Hasher::Crc32(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+    
+    // Lowercase hex digest; CRC32 is the usual 8 hex digits.
+    pub fn finalize(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Md5(context) => format!("{:x}", context.compute()),
+            Hasher::Crc32(hasher) => format!("{:08x}", hasher.finalize()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+    
+    // Feeds the first `len` bytes of a file, e.g. the part of a download
+    // that an earlier run already wrote to disk.
+    pub async fn update_from_file(&mut self, path: &Path, len: u64) -> Result<()> {
+        let file = tokio::fs::File::open(path).await?;
+        let mut reader = file.take(len);
+        let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+        
+        loop {
+            let read = reader.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            self.update(&buffer[..read]);
+        }
+        
+        Ok(())
+    }
+}
+
+// Hashes a file in fixed-size reads instead of loading it into memory.
+pub async fn hash_file(path: &Path, algorithm: HashAlgorithm) -> Result<String> {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update_from_file(path, u64::MAX).await?;
+    Ok(hasher.finalize())
+}
+
+// Compares hex digests ignoring case and an optional 0x prefix, which some
+// patch servers put in front of CRC32 values.
+pub fn digests_match(actual: &str, expected: &str) -> bool {
+    let normalize = |digest: &str| {
+        let digest = digest.trim();
+        digest
+            .strip_prefix("0x")
+            .or_else(|| digest.strip_prefix("0X"))
+            .unwrap_or(digest)
+            .to_ascii_lowercase()
+    };
+    normalize(actual) == normalize(expected)
+}
+
+// A checksum from the patch list together with the algorithm it was made with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedChecksum {
+    pub value: String,
+    pub algorithm: HashAlgorithm,
+}
+
+impl ExpectedChecksum {
+    pub fn new(value: &str, algorithm: Option<&str>) -> Result<Self> {
+        Ok(ExpectedChecksum {
+            value: value.to_string(),
+            algorithm: HashAlgorithm::parse(algorithm)?,
+        })
+    }
+    
+    pub fn matches(&self, digest: &str) -> bool {
+        digests_match(digest, &self.value)
+    }
+}
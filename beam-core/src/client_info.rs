@@ -0,0 +1,129 @@
+use crate::config::ClientInfoConfig;
+use crate::{Config, Error, Result};
+
+const DEFAULT_FILE_NAME: &str = "clientinfo.xml";
+const DEFAULT_SERVICE_TYPE: &str = "korea";
+const DEFAULT_SERVER_TYPE: &str = "primary";
+const DEFAULT_LANGTYPE: u32 = 1;
+const DEFAULT_VERSION: u32 = 55;
+
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub file_name: String,
+    pub write_to_grf: bool,
+    pub display_name: String,
+    pub address: String,
+    pub port: u16,
+    pub service_type: String,
+    pub server_type: String,
+    pub langtype: u32,
+    pub version: u32,
+    pub registration_web: Option<String>,
+    pub loading_images: Vec<String>,
+}
+
+impl ClientInfo {
+    // Returns None when clientinfo generation isn't configured or is disabled.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let Some(server) = &config.server else {
+            return Ok(None);
+        };
+        
+        let info_config: &ClientInfoConfig = match &server.client_info {
+            Some(info) if info.enabled => info,
+            _ => return Ok(None),
+        };
+        
+        let file_name = info_config
+            .file_name
+            .clone()
+            .unwrap_or_else(|| DEFAULT_FILE_NAME.to_string());
+        
+        if file_name.contains(['/', '\\']) || !file_name.to_lowercase().ends_with(".xml") {
+            return Err(Error::InvalidConfig(format!(
+                "Invalid client info file name: {}",
+                file_name
+            )));
+        }
+        
+        Ok(Some(ClientInfo {
+            file_name,
+            write_to_grf: info_config.write_to_grf,
+            display_name: config
+                .app
+                .server_name
+                .clone()
+                .unwrap_or_else(|| config.app.name.clone()),
+            address: server.login_server_ip.clone(),
+            port: server.login_server_port,
+            service_type: info_config
+                .service_type
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SERVICE_TYPE.to_string()),
+            server_type: info_config
+                .server_type
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SERVER_TYPE.to_string()),
+            langtype: info_config.langtype.unwrap_or(DEFAULT_LANGTYPE),
+            version: info_config.version.unwrap_or(DEFAULT_VERSION),
+            registration_web: config.app.register_url.clone(),
+            loading_images: info_config.loading_images.clone().unwrap_or_default(),
+        }))
+    }
+    
+    // Path of the file inside the GRF, using the client's backslash separators.
+    pub fn grf_path(&self) -> String {
+        format!("data\\{}", self.file_name)
+    }
+    
+    pub fn render(&self) -> String {
+        let mut xml = String::new();
+        
+        // Non-ASCII text is emitted as character references, so the euc-kr
+        // declaration the client expects stays truthful.
+        xml.push_str("<?xml version=\"1.0\" encoding=\"euc-kr\" ?>\n");
+        xml.push_str("<clientinfo>\n");
+        xml.push_str(&format!("\t<desc>{}</desc>\n", escape_xml(&self.display_name)));
+        xml.push_str(&format!("\t<servicetype>{}</servicetype>\n", escape_xml(&self.service_type)));
+        xml.push_str(&format!("\t<servertype>{}</servertype>\n", escape_xml(&self.server_type)));
+        xml.push_str("\t<connection>\n");
+        xml.push_str(&format!("\t\t<display>{}</display>\n", escape_xml(&self.display_name)));
+        xml.push_str(&format!("\t\t<address>{}</address>\n", escape_xml(&self.address)));
+        xml.push_str(&format!("\t\t<port>{}</port>\n", self.port));
+        xml.push_str(&format!("\t\t<version>{}</version>\n", self.version));
+        xml.push_str(&format!("\t\t<langtype>{}</langtype>\n", self.langtype));
+        
+        if let Some(url) = &self.registration_web {
+            xml.push_str(&format!("\t\t<registrationweb>{}</registrationweb>\n", escape_xml(url)));
+        }
+        
+        if !self.loading_images.is_empty() {
+            xml.push_str("\t\t<loading>\n");
+            for image in &self.loading_images {
+                xml.push_str(&format!("\t\t\t<image>{}</image>\n", escape_xml(image)));
+            }
+            xml.push_str("\t\t</loading>\n");
+        }
+        
+        xml.push_str("\t</connection>\n");
+        xml.push_str("</clientinfo>\n");
+        
+        xml
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c if c.is_ascii() => escaped.push(c),
+            c => escaped.push_str(&format!("&#{};", c as u32)),
+        }
+    }
+    escaped
+}
@@ -43,6 +43,7 @@ pub struct AppConfig {
     pub social_links: Option<Vec<SocialLink>>,
     pub discord_url: Option<String>,
     pub register_url: Option<String>,
+    pub wine_prefix: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,6 +53,98 @@ pub struct PatcherConfig {
     pub target_grf: String,
     pub allow_manual_patch: bool,
     pub verify_checksums: bool,
+    // Base64 Ed25519 key; when set, the patch list and file manifest must be signed.
+    pub public_key: Option<String>,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub bandwidth: BandwidthConfig,
+    #[serde(default)]
+    pub prefetch: PrefetchConfig,
+    #[serde(default)]
+    pub batch: BatchConfig,
+}
+
+// How far patch downloads may run ahead of patch application.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrefetchConfig {
+    // Downloaded patches waiting to be applied; at least 1.
+    pub max_patches: usize,
+    // Disk space the waiting patches may take up together, in MB.
+    pub max_mb: u64,
+}
+
+impl Default for PrefetchConfig {
+    fn default() -> Self {
+        PrefetchConfig {
+            max_patches: 2,
+            max_mb: 512,
+        }
+    }
+}
+
+// How many consecutive patches for the same GRF are merged into one rebuild.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatchConfig {
+    // Patches per GRF save; 1 saves after every patch.
+    pub max_patches: usize,
+    // Patched file data held in memory until the save, in MB.
+    pub max_mb: u64,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            max_patches: 50,
+            max_mb: 256,
+        }
+    }
+}
+
+// Download speed limits in KB/s; None means unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BandwidthConfig {
+    pub max_kb_per_sec: Option<u64>,
+    // Applies while the game client launched from the patcher is running.
+    pub in_game_max_kb_per_sec: Option<u64>,
+}
+
+impl BandwidthConfig {
+    // The stricter of the limits that apply right now.
+    pub fn effective_kb_per_sec(&self, game_running: bool) -> Option<u64> {
+        let in_game = self.in_game_max_kb_per_sec.filter(|_| game_running);
+        match (self.max_kb_per_sec, in_game) {
+            (Some(global), Some(in_game)) => Some(global.min(in_game)),
+            (limit, None) | (None, limit) => limit,
+        }
+        .filter(|kb| *kb > 0)
+    }
+}
+
+// How often transient network failures (timeouts, 5xx, dropped connections)
+// are retried before giving up on a mirror or request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    // Fraction of each delay that is randomized, 0.0 to 1.0.
+    pub jitter: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_retries: 3,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 15_000,
+            jitter: 0.5,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -122,6 +215,37 @@ pub struct ServerConfig {
     pub char_server_port: u16,
     pub map_server_ip: String,
     pub map_server_port: u16,
+    pub client_info: Option<ClientInfoConfig>,
+}
+
+// Every field may be left out; a `client_info:` block on its own turns
+// generation on with the defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientInfoConfig {
+    pub enabled: bool,
+    pub file_name: Option<String>,
+    pub write_to_grf: bool,
+    pub service_type: Option<String>,
+    pub server_type: Option<String>,
+    pub langtype: Option<u32>,
+    pub version: Option<u32>,
+    pub loading_images: Option<Vec<String>>,
+}
+
+impl Default for ClientInfoConfig {
+    fn default() -> Self {
+        ClientInfoConfig {
+            enabled: true,
+            file_name: None,
+            write_to_grf: false,
+            service_type: None,
+            server_type: None,
+            langtype: None,
+            version: None,
+            loading_images: None,
+        }
+    }
 }
 
 impl Config {
@@ -173,6 +297,7 @@ impl Config {
                 social_links: None,
                 discord_url: None,
                 register_url: None,
+                wine_prefix: None,
             },
             patcher: PatcherConfig {
                 mirrors: vec![
@@ -186,6 +311,11 @@ impl Config {
                 target_grf: "data.grf".to_string(),
                 allow_manual_patch: true,
                 verify_checksums: true,
+                public_key: None,
+                retry: RetryConfig::default(),
+                bandwidth: BandwidthConfig::default(),
+                prefetch: PrefetchConfig::default(),
+                batch: BatchConfig::default(),
             },
             ui: UiConfig {
                 theme: "default".to_string(),
@@ -224,7 +354,23 @@ impl Config {
                 char_server_port: 6121,
                 map_server_ip: "127.0.0.1".to_string(),
                 map_server_port: 5121,
+                client_info: None,
             }),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn partial_client_info_block_uses_defaults() {
+        let info: ClientInfoConfig = serde_yaml::from_str("version: 60").unwrap();
+        
+        assert!(info.enabled);
+        assert!(!info.write_to_grf);
+        assert_eq!(info.version, Some(60));
+        assert_eq!(info.file_name, None);
+    }
+}
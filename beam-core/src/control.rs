@@ -0,0 +1,161 @@
+use crate::{Error, Result};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    Running,
+    Paused,
+    Cancelled,
+}
+
+// Cooperative pause and cancel for a patch run. Downloads and patch
+// application call `checkpoint` at points where stopping is safe; a GRF save
+// is never interrupted, the request takes effect once it's written. Clones
+// share the same state.
+#[derive(Debug, Clone)]
+pub struct PatchControl {
+    state: Arc<watch::Sender<RunState>>,
+}
+
+impl Default for PatchControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PatchControl {
+    pub fn new() -> Self {
+        PatchControl {
+            state: Arc::new(watch::Sender::new(RunState::Running)),
+        }
+    }
+    
+    pub fn state(&self) -> RunState {
+        *self.state.borrow()
+    }
+    
+    // Pausing a cancelled run keeps it cancelled.
+    pub fn pause(&self) {
+        self.state.send_if_modified(|state| {
+            let changed = *state == RunState::Running;
+            if changed {
+                *state = RunState::Paused;
+            }
+            changed
+        });
+    }
+    
+    pub fn resume(&self) {
+        self.state.send_if_modified(|state| {
+            let changed = *state == RunState::Paused;
+            if changed {
+                *state = RunState::Running;
+            }
+            changed
+        });
+    }
+    
+    pub fn cancel(&self) {
+        self.state.send_replace(RunState::Cancelled);
+    }
+    
+    // Clears a pause or cancel left over from an earlier run.
+    pub fn reset(&self) {
+        self.state.send_replace(RunState::Running);
+    }
+    
+    // Waits while paused; fails with Error::Cancelled once cancelled.
+    pub async fn checkpoint(&self) -> Result<()> {
+        let mut state = self.state.subscribe();
+        
+        loop {
+            match *state.borrow_and_update() {
+                RunState::Running => return Ok(()),
+                RunState::Cancelled => return Err(Error::Cancelled),
+                RunState::Paused => {}
+            }
+            
+            // The sender lives as long as `self`, so this only waits.
+            let _ = state.changed().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    
+    #[test]
+    fn pause_resume_and_cancel_transitions() {
+        let control = PatchControl::new();
+        assert_eq!(control.state(), RunState::Running);
+        
+        // Resuming a running patch changes nothing.
+        control.resume();
+        assert_eq!(control.state(), RunState::Running);
+        
+        control.pause();
+        assert_eq!(control.state(), RunState::Paused);
+        control.resume();
+        assert_eq!(control.state(), RunState::Running);
+        
+        control.pause();
+        control.cancel();
+        assert_eq!(control.state(), RunState::Cancelled);
+        
+        // A cancelled run can't be paused or resumed, only reset.
+        control.pause();
+        assert_eq!(control.state(), RunState::Cancelled);
+        control.resume();
+        assert_eq!(control.state(), RunState::Cancelled);
+        control.reset();
+        assert_eq!(control.state(), RunState::Running);
+    }
+    
+    #[test]
+    fn clones_share_state() {
+        let control = PatchControl::new();
+        let other = control.clone();
+        
+        other.pause();
+        assert_eq!(control.state(), RunState::Paused);
+    }
+    
+    #[tokio::test]
+    async fn checkpoint_waits_while_paused() {
+        let control = PatchControl::new();
+        assert!(control.checkpoint().await.is_ok());
+        
+        control.pause();
+        let waiting = tokio::spawn({
+            let control = control.clone();
+            async move { control.checkpoint().await }
+        });
+        
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        
+        control.resume();
+        assert!(waiting.await.unwrap().is_ok());
+    }
+    
+    #[tokio::test]
+    async fn cancel_releases_paused_checkpoint() {
+        let control = PatchControl::new();
+        control.pause();
+        
+        let waiting = tokio::spawn({
+            let control = control.clone();
+            async move { control.checkpoint().await }
+        });
+        
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        control.cancel();
+        assert!(matches!(waiting.await.unwrap(), Err(Error::Cancelled)));
+        assert!(matches!(control.checkpoint().await, Err(Error::Cancelled)));
+    }
+}
@@ -1,21 +1,49 @@
 use crate::{Config, Error, Result};
+use crate::checksum::{self, ExpectedChecksum, HashAlgorithm, Hasher};
+use crate::config::MirrorConfig;
+use crate::mirror_health::{MirrorHealth, MirrorStatus};
+use crate::rate_limit::RateLimiter;
+use crate::session::DownloadSession;
+use crate::control::PatchControl;
+use crate::parallel_downloader::{ParallelDownloader, RemoteFileInfo};
+use crate::partial_download::{PartialDownload, Validators};
+use crate::patch_manifest::{self, PatchTarget};
+use crate::patch_state::PatchState;
+use crate::retry;
+use crate::signing::SignatureVerifier;
 use futures::StreamExt;
-use reqwest::Client;
-use sha2::{Digest, Sha256};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
-use tokio::fs::File;
+use std::time::Instant;
+use tokio::fs::{File, OpenOptions};
 use tokio::io::AsyncWriteExt;
 use tracing::{debug, info, warn};
-use std::collections::HashSet;
+
+// How often a single-stream download records its progress for resuming.
+const CHECKPOINT_INTERVAL: u64 = 4 * 1024 * 1024;
 
 pub struct Downloader {
     client: Client,
+    parallel: ParallelDownloader,
     config: Config,
-    cache_file: PathBuf,
+    cache_dir: PathBuf,
+    state: PatchState,
+    health: MirrorHealth,
+    limiter: RateLimiter,
+    control: PatchControl,
 }
 
 impl Downloader {
     pub fn new(config: Config) -> Result<Self> {
+        let session = DownloadSession::new(&config);
+        Self::with_session(config, session)
+    }
+    
+    // Shares mirror health, the bandwidth limit and pause/cancel control with
+    // other downloaders, so a mirror that failed for one patch run stays
+    // deprioritized for the rest of the session.
+    pub fn with_session(config: Config, session: DownloadSession) -> Result<Self> {
         let client = Client::builder()
             .user_agent("Beam-Patcher/1.0")
             .connect_timeout(std::time::Duration::from_secs(30))
@@ -25,207 +53,113 @@ impl Downloader {
             .pool_max_idle_per_host(10)
             .build()?;
         
-        let cache_file = if let Some(game_dir) = &config.app.game_directory {
-            Path::new(game_dir).join(".patch_cache").join("applied_patches.txt")
+        let cache_dir = if let Some(game_dir) = &config.app.game_directory {
+            Path::new(game_dir).join(".patch_cache")
         } else {
-            let exe_dir = crate::get_executable_dir()?;
-            exe_dir.join(".patch_cache").join("applied_patches.txt")
+            crate::get_executable_dir()?.join(".patch_cache")
         };
         
-        if let Some(parent) = cache_file.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-        
-        Ok(Downloader { client, config, cache_file })
+        std::fs::create_dir_all(&cache_dir)?;
+        let state = PatchState::new(&cache_dir);
+        
+        let parallel = ParallelDownloader::with_client(
+            client.clone(),
+            config.clone(),
+            session.rate_limiter.clone(),
+            session.control.clone(),
+        );
+        
+        Ok(Downloader {
+            client,
+            parallel,
+            config,
+            cache_dir,
+            state,
+            health: session.mirror_health,
+            limiter: session.rate_limiter,
+            control: session.control,
+        })
     }
     
-    fn load_applied_patches(&self) -> Result<HashSet<String>> {
-        let mut applied = HashSet::new();
-        
-        if self.cache_file.exists() {
-            let content = std::fs::read_to_string(&self.cache_file)?;
-            for line in content.lines() {
-                let line = line.trim();
-                if !line.is_empty() {
-                    applied.insert(line.to_string());
-                }
-            }
-        }
+    pub fn mark_patch_applied(&self, patch: &PatchInfo, target: Option<&str>) -> Result<()> {
+        self.state.record(patch, target)?;
+        info!("Marked patch as applied: {}", patch.filename);
         
-        Ok(applied)
+        Ok(())
     }
     
-    pub fn mark_patch_applied(&self, filename: &str) -> Result<()> {
-        let mut applied = self.load_applied_patches()?;
-        applied.insert(filename.to_string());
-        
-        let content = applied.iter()
-            .map(|s| s.as_str())
-            .collect::<Vec<_>>()
-            .join("\n");
-        
-        std::fs::write(&self.cache_file, content)?;
-        info!("Marked patch as applied: {}", filename);
-        
-        Ok(())
+    pub fn patch_state(&self) -> &PatchState {
+        &self.state
+    }
+    
+    // Directory holding the patcher's bookkeeping for this game install.
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
     }
     
     pub fn clear_cache(&self) -> Result<()> {
-        if self.cache_file.exists() {
-            std::fs::remove_file(&self.cache_file)?;
-        }
-        Ok(())
+        self.state.clear()
     }
     
-    pub async fn download_file(
-        &self,
-        filename: &str,
-        destination: &Path,
-    ) -> Result<PathBuf> {
-        let mut mirrors = self.config.patcher.mirrors.clone();
-        mirrors.sort_by_key(|m| m.priority);
-        
-        let mut last_error = None;
-        
-        for mirror in &mirrors {
-            if mirror.url.is_empty() {
-                warn!("Skipping mirror {} with empty URL", mirror.name);
-                continue;
-            }
-            
-            let url = format!("{}/{}", mirror.url, filename);
-            info!("Attempting download from mirror: {} ({})", mirror.name, url);
-            
-            match self.download_from_url(&url, destination).await {
-                Ok(path) => {
-                    info!("Successfully downloaded from mirror: {}", mirror.name);
-                    return Ok(path);
-                }
-                Err(e) => {
-                    warn!("Failed to download from mirror {}: {}", mirror.name, e);
-                    last_error = Some(e);
-                }
-            }
-        }
-        
-        Err(last_error.unwrap_or_else(|| {
-            Error::DownloadFailed("All mirrors failed".to_string())
-        }))
+    pub fn mirror_health(&self) -> &MirrorHealth {
+        &self.health
     }
     
-    async fn download_from_url(
+    // Re-probes every mirror and returns their status in download order.
+    pub async fn probe_mirrors(&self) -> Vec<MirrorStatus> {
+        self.health.probe_all(&self.client, &self.config.patcher.mirrors).await;
+        self.mirror_status()
+    }
+    
+    pub fn mirror_status(&self) -> Vec<MirrorStatus> {
+        self.health.snapshot(&self.config.patcher.mirrors)
+    }
+    
+    pub async fn download_file(
         &self,
-        url: &str,
+        filename: &str,
         destination: &Path,
     ) -> Result<PathBuf> {
-        debug!("Downloading: {}", url);
-        
-        let response = self.client.get(url).send().await?;
-        
-        if !response.status().is_success() {
-            return Err(Error::DownloadFailed(format!(
-                "HTTP error: {}",
-                response.status()
-            )));
-        }
-        
-        let _total_size = response.content_length().unwrap_or(0);
-        let mut _downloaded: u64 = 0;
-        
-        let filepath = destination.to_path_buf();
-        tokio::fs::create_dir_all(filepath.parent().unwrap()).await?;
-        let mut file = File::create(&filepath).await?;
-        
-        let mut stream = response.bytes_stream();
-        
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            file.write_all(&chunk).await?;
-            
-            _downloaded += chunk.len() as u64;
-        }
-        
-        file.flush().await?;
-        
-        info!("Download completed: {:?}", filepath);
-        Ok(filepath)
+        self.download_file_with_progress(filename, destination, |_, _| {}).await
     }
     
     pub async fn download_patch_list(&self) -> Result<Vec<PatchInfo>> {
         let url = &self.config.patcher.patch_list_url;
         info!("Downloading patch list from: {}", url);
         
-        let response = self.client.get(url).send().await?;
+        let retry = &self.config.patcher.retry;
+        let body = retry::get_bytes(&self.client, url, retry).await?;
         
-        if !response.status().is_success() {
-            return Err(Error::DownloadFailed(format!(
-                "Failed to download patch list: HTTP {}",
-                response.status()
-            )));
+        if let Some(verifier) = SignatureVerifier::from_config(&self.config)? {
+            verifier.fetch_and_verify(&self.client, url, &body, retry).await?;
         }
         
-        let content = response.text().await?;
-        let all_patches = self.parse_patch_list(&content)?;
+        let content = String::from_utf8_lossy(&body);
+        let all_patches = patch_manifest::parse_patch_list(&content)?;
+        let total = all_patches.len();
         
-        let applied_patches = self.load_applied_patches()?;
-        
-        let pending_patches: Vec<PatchInfo> = all_patches
-            .into_iter()
-            .filter(|patch| !applied_patches.contains(&patch.filename))
-            .collect();
+        let pending_patches = self.state.pending(all_patches)?;
         
         info!("Found {} total patches, {} already applied, {} pending", 
-              pending_patches.len() + applied_patches.len(),
-              applied_patches.len(),
+              total,
+              total - pending_patches.len(),
               pending_patches.len());
         
         Ok(pending_patches)
     }
     
-    fn parse_patch_list(&self, content: &str) -> Result<Vec<PatchInfo>> {
-        let mut patches = Vec::new();
-        
-        for line in content.lines() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
-            
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.is_empty() {
-                continue;
-            }
-            
-            let filename = parts[0].to_string();
-            let checksum = if parts.len() > 1 {
-                Some(parts[1].to_string())
-            } else {
-                None
-            };
-            let size = if parts.len() > 2 {
-                parts[2].parse::<u64>().ok()
-            } else {
-                None
-            };
-            
-            patches.push(PatchInfo { filename, checksum, size });
-        }
-        
-        Ok(patches)
+    pub async fn verify_checksum(&self, file_path: &Path, expected: &str) -> Result<bool> {
+        self.verify_checksum_with(file_path, expected, None).await
     }
     
-    pub async fn verify_checksum(&self, file_path: &Path, expected: &str) -> Result<bool> {
-        if !self.config.patcher.verify_checksums {
-            return Ok(true);
-        }
-        
-        let data = tokio::fs::read(file_path).await?;
-        let mut hasher = Sha256::new();
-        hasher.update(&data);
-        let result = hasher.finalize();
-        let hash = format!("{:x}", result);
-        
-        Ok(hash == expected)
+    pub async fn verify_checksum_with(
+        &self,
+        file_path: &Path,
+        expected: &str,
+        algorithm: Option<&str>,
+    ) -> Result<bool> {
+        let expected = ExpectedChecksum::new(expected, algorithm)?;
+        self.matches_expected(file_path, Some(&expected)).await
     }
     
     pub async fn download_file_with_progress<F>(
@@ -237,27 +171,140 @@ impl Downloader {
     where
         F: FnMut(u64, u64) + Send + 'static,
     {
-        let mut mirrors = self.config.patcher.mirrors.clone();
-        mirrors.sort_by_key(|m| m.priority);
+        self.download_from_mirrors(filename, destination, None, &mut progress_callback).await
+    }
+    
+    // Downloads any file the mirrors serve and checks it against `checksum`,
+    // whatever verify_checksums says. Used to fetch damaged game files again.
+    pub async fn download_checked(
+        &self,
+        filename: &str,
+        destination: &Path,
+        checksum: &str,
+        algorithm: Option<&str>,
+    ) -> Result<PathBuf> {
+        let expected = ExpectedChecksum::new(checksum, algorithm)?;
+        self.download_from_mirrors(filename, destination, Some(&expected), &mut |_, _| {}).await
+    }
+    
+    // Downloads a patch and checks it against the patch list checksum. A file
+    // assembled from several mirrors is only accepted if the whole file matches.
+    pub async fn download_patch<F>(
+        &self,
+        patch: &PatchInfo,
+        destination: &Path,
+        mut progress_callback: F,
+    ) -> Result<PathBuf>
+    where
+        F: FnMut(u64, u64) + Send + 'static,
+    {
+        let expected = patch
+            .checksum
+            .as_deref()
+            .filter(|_| self.config.patcher.verify_checksums)
+            .map(|checksum| ExpectedChecksum::new(checksum, patch.hash_algorithm.as_deref()))
+            .transpose()?;
+        
+        self.download_from_mirrors(&patch.filename, destination, expected.as_ref(), &mut progress_callback).await
+    }
+    
+    // Every download is checked against `expected` when one is given; callers
+    // leave it out when verify_checksums is off.
+    async fn download_from_mirrors<F>(
+        &self,
+        filename: &str,
+        destination: &Path,
+        expected: Option<&ExpectedChecksum>,
+        progress_callback: &mut F,
+    ) -> Result<PathBuf>
+    where
+        F: FnMut(u64, u64) + Send,
+    {
+        let mirrors = self.ordered_mirrors().await;
+        
+        // A fallback or another mirror may start over from fewer bytes than
+        // were already reported; hold the count until it catches up.
+        let mut reported = 0;
+        let progress_callback = &mut |downloaded: u64, total: u64| {
+            reported = reported.max(downloaded);
+            progress_callback(reported, total);
+        };
         
-        let mut last_error = None;
+        // Single-stream downloads hash the data as it arrives, so the file
+        // doesn't have to be read back afterwards.
+        let algorithm = expected.map(|expected| expected.algorithm);
         
-        for mirror in &mirrors {
-            if mirror.url.is_empty() {
-                warn!("Skipping mirror {} with empty URL", mirror.name);
-                continue;
-            }
+        let urls: Vec<String> = mirrors
+            .iter()
+            .map(|mirror| format!("{}/{}", mirror.url, filename))
+            .collect();
+        
+        // Large files are striped across every mirror that serves them with
+        // range support, so the per-mirror bandwidth caps add up. Mirrors on
+        // cooldown are left out so a dead one doesn't stall the size probe.
+        let striping_urls: Vec<String> = mirrors
+            .iter()
+            .zip(&urls)
+            .filter(|(mirror, _)| self.health.is_available(&mirror.url))
+            .map(|(_, url)| url.clone())
+            .collect();
+        
+        if let Some((sources, total_size)) = self.striping_sources(&striping_urls).await {
+            let started = Instant::now();
             
-            let url = format!("{}/{}", mirror.url, filename);
+            match self.parallel
+                .download_chunks(&sources, destination, total_size, progress_callback)
+                .await
+            {
+                Ok(path) => {
+                    let verified = match expected {
+                        Some(expected) => expected.matches(&checksum::hash_file(&path, expected.algorithm).await?),
+                        None => true,
+                    };
+                    if verified {
+                        // Chunks are spread evenly, so each source is credited an equal share.
+                        let share = total_size / sources.len() as u64;
+                        for (mirror, url) in mirrors.iter().zip(&urls) {
+                            if sources.iter().any(|(source, _)| source == url) {
+                                self.health.record_success(&mirror.url, share, started.elapsed());
+                            }
+                        }
+                        return Ok(path);
+                    }
+                    warn!("{} assembled from {} mirror(s) failed checksum verification", filename, sources.len());
+                }
+                Err(Error::Cancelled) => return Err(Error::Cancelled),
+                Err(e) => warn!("Parallel download of {} failed: {}", filename, e),
+            }
+            info!("Falling back to downloading {} from one mirror at a time", filename);
+        }
+        
+        let mut last_error = None;
+        
+        for (mirror, url) in mirrors.iter().zip(&urls) {
             info!("Attempting download from mirror: {} ({})", mirror.name, url);
+            let started = Instant::now();
             
-            match self.download_from_url_with_progress(&url, destination, &mut progress_callback).await {
-                Ok(path) => {
-                    info!("Successfully downloaded from mirror: {}", mirror.name);
-                    return Ok(path);
+            match self.download_with_retry(url, destination, algorithm, progress_callback).await {
+                Ok(streamed) => {
+                    let verified = match (expected, &streamed.digest) {
+                        (Some(expected), Some(digest)) => expected.matches(digest),
+                        _ => true,
+                    };
+                    
+                    if verified {
+                        info!("Successfully downloaded from mirror: {}", mirror.name);
+                        self.health.record_success(&mirror.url, streamed.bytes, started.elapsed());
+                        return Ok(streamed.path);
+                    }
+                    warn!("Checksum mismatch for {} from mirror {}", filename, mirror.name);
+                    self.health.record_failure(&mirror.url, "checksum mismatch");
+                    last_error = Some(Error::PatchFailed(format!("Checksum mismatch for {}", filename)));
                 }
+                Err(Error::Cancelled) => return Err(Error::Cancelled),
                 Err(e) => {
                     warn!("Failed to download from mirror {}: {}", mirror.name, e);
+                    self.health.record_failure(&mirror.url, &e.to_string());
                     last_error = Some(e);
                 }
             }
@@ -268,45 +315,390 @@ impl Downloader {
         }))
     }
     
-    async fn download_from_url_with_progress<F>(
+    // Retries transient failures on one mirror before the caller moves on to
+    // the next. Each retry resumes from the .part file the last attempt left.
+    async fn download_with_retry<F>(
         &self,
         url: &str,
         destination: &Path,
+        algorithm: Option<HashAlgorithm>,
         progress_callback: &mut F,
-    ) -> Result<PathBuf>
+    ) -> Result<StreamedFile>
+    where
+        F: FnMut(u64, u64) + Send,
+    {
+        // Attempts run one after another, so they can share the callback.
+        let progress = &std::sync::Mutex::new(progress_callback);
+        let what = format!("Download from {}", url);
+        
+        retry::with_retry(&self.config.patcher.retry, &what, || async move {
+            self.control.checkpoint().await?;
+            let mut report = |downloaded, total| (*progress.lock().unwrap())(downloaded, total);
+            self.download_single_stream(url, destination, algorithm, &mut report).await
+        })
+        .await
+    }
+    
+    // Configured mirrors in the order this session should try them, probing
+    // them first if that hasn't happened yet.
+    async fn ordered_mirrors(&self) -> Vec<MirrorConfig> {
+        let mut mirrors = self.config.patcher.mirrors.clone();
+        mirrors.retain(|mirror| {
+            if mirror.url.is_empty() {
+                warn!("Skipping mirror {} with empty URL", mirror.name);
+            }
+            !mirror.url.is_empty()
+        });
+        
+        self.health.ensure_probed(&self.client, &mirrors).await;
+        self.health.order(mirrors)
+    }
+    
+    // Probes all mirrors at once and returns those that accept range requests
+    // and report the same size as the highest priority one, with that size.
+    async fn striping_sources(&self, urls: &[String]) -> Option<(Vec<(String, RemoteFileInfo)>, u64)> {
+        let probes = futures::future::join_all(urls.iter().map(|url| self.parallel.probe(url))).await;
+        
+        let mut candidates = urls.iter().zip(probes).filter_map(|(url, probe)| match probe {
+            Ok(remote) if remote.supports_chunked() => Some((url.clone(), remote)),
+            Ok(_) => {
+                debug!("No range support at {}", url);
+                None
+            }
+            Err(e) => {
+                debug!("HEAD request to {} failed: {}", url, e);
+                None
+            }
+        });
+        
+        let first = candidates.next()?;
+        let total_size = first.1.size?;
+        let mut striped = vec![first];
+        
+        for (url, remote) in candidates {
+            if remote.size == Some(total_size) {
+                striped.push((url, remote));
+            } else {
+                warn!("Mirror size mismatch for {} ({:?} vs {} bytes), not striping from it", url, remote.size, total_size);
+            }
+        }
+        
+        Some((striped, total_size))
+    }
+    
+    // Hashes a file already on disk, for downloads assembled out of order.
+    async fn matches_expected(&self, path: &Path, expected: Option<&ExpectedChecksum>) -> Result<bool> {
+        match expected {
+            Some(expected) if self.config.patcher.verify_checksums => {
+                let digest = checksum::hash_file(path, expected.algorithm).await?;
+                Ok(expected.matches(&digest))
+            }
+            _ => Ok(true),
+        }
+    }
+    
+    // Streams `url` into `<destination>.part`, moved into place once complete.
+    // A partial download left by an earlier run is continued with a Range
+    // request guarded by If-Range, so a file changed on the server since then
+    // comes back whole instead of being spliced onto stale bytes. With an
+    // algorithm given, the file is hashed while it streams in.
+    async fn download_single_stream<F>(
+        &self,
+        url: &str,
+        destination: &Path,
+        algorithm: Option<HashAlgorithm>,
+        progress_callback: &mut F,
+    ) -> Result<StreamedFile>
     where
         F: FnMut(u64, u64),
     {
-        let response = self.client.get(url).send().await?;
+        let previous = PartialDownload::load(destination);
+        if let Some(partial) = previous.as_ref().filter(|partial| partial.is_complete()) {
+            if let Some(streamed) = self.finish_complete_part(destination, partial, algorithm, progress_callback).await? {
+                return Ok(streamed);
+            }
+        }
         
-        if !response.status().is_success() {
-            return Err(Error::DownloadFailed(format!(
-                "HTTP error: {}",
-                response.status()
+        let resume = previous.as_ref().and_then(|partial| {
+            let if_range = partial.sources.get(url)?.if_range()?.to_string();
+            let offset = partial.contiguous_prefix();
+            (offset > 0).then_some((offset, if_range))
+        });
+        
+        let mut request = self.client.get(url);
+        if let Some((offset, if_range)) = &resume {
+            request = request
+                .header(reqwest::header::RANGE, format!("bytes={}-", offset))
+                .header(reqwest::header::IF_RANGE, if_range.as_str());
+        }
+        
+        let response = request.send().await?;
+        
+        // The server has nothing past the recorded offset, so what is on disk
+        // can't be continued; drop it and let the retry start over.
+        if resume.is_some() && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            PartialDownload::discard(destination)?;
+            return Err(Error::Interrupted(format!(
+                "{} rejected the resume range, starting over",
+                url
             )));
         }
         
-        let total_size = response.content_length().unwrap_or(0);
-        let mut downloaded = 0u64;
+        if !response.status().is_success() {
+            return Err(Error::Http {
+                url: url.to_string(),
+                status: response.status().as_u16(),
+            });
+        }
+        
+        // A mirror that answers with some other range would splice the wrong
+        // bytes onto the .part file; drop it and let the retry start over.
+        if let Some((offset, _)) = &resume {
+            if response.status() == StatusCode::PARTIAL_CONTENT
+                && content_range_start(response.headers()) != Some(*offset)
+            {
+                PartialDownload::discard(destination)?;
+                return Err(Error::Interrupted(format!(
+                    "{} did not resume at byte {}, starting over",
+                    url, offset
+                )));
+            }
+        }
+        
+        if let Some(parent) = destination.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        
+        let part_path = PartialDownload::part_path(destination);
+        let body_size = response.content_length();
+        
+        let (mut partial, mut file, mut downloaded) = match (resume, previous) {
+            (Some((offset, _)), Some(mut partial)) if response.status() == StatusCode::PARTIAL_CONTENT => {
+                info!("Resuming {} at byte {}", url, offset);
+                partial.completed = vec![(0, offset)];
+                
+                let file = OpenOptions::new().append(true).open(&part_path).await?;
+                file.set_len(offset).await?;
+                (partial, file, offset)
+            }
+            _ => {
+                PartialDownload::discard(destination)?;
+                let mut partial = PartialDownload::new(body_size);
+                partial.sources.insert(url.to_string(), Validators::from_headers(response.headers()));
+                (partial, File::create(&part_path).await?, 0)
+            }
+        };
+        
+        let total_size = body_size.map(|size| size + downloaded).unwrap_or(0);
+        partial.save(destination)?;
+        
+        let mut hasher = algorithm.map(Hasher::new);
+        if let Some(hasher) = hasher.as_mut().filter(|_| downloaded > 0) {
+            hasher.update_from_file(&part_path, downloaded).await?;
+        }
         
-        let mut file = File::create(destination).await?;
         let mut stream = response.bytes_stream();
+        let resumed_at = downloaded;
+        let mut checkpointed = downloaded;
         
         while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    checkpoint(&mut file, &mut partial, destination, downloaded).await?;
+                    return Err(Error::Interrupted(format!("{} after {} bytes: {}", url, downloaded, e)));
+                }
+            };
+            
             file.write_all(&chunk).await?;
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&chunk);
+            }
             downloaded += chunk.len() as u64;
             progress_callback(downloaded, total_size);
+            self.limiter.acquire(chunk.len()).await;
+            
+            // Keep the progress made so far for the next run.
+            if let Err(e) = self.control.checkpoint().await {
+                checkpoint(&mut file, &mut partial, destination, downloaded).await?;
+                return Err(e);
+            }
+            
+            if downloaded - checkpointed >= CHECKPOINT_INTERVAL {
+                checkpoint(&mut file, &mut partial, destination, downloaded).await?;
+                checkpointed = downloaded;
+            }
+        }
+        
+        if body_size.is_some() && downloaded != total_size {
+            checkpoint(&mut file, &mut partial, destination, downloaded).await?;
+            return Err(Error::Interrupted(format!(
+                "connection closed after {} of {} bytes",
+                downloaded, total_size
+            )));
         }
         
         file.flush().await?;
-        Ok(destination.to_path_buf())
+        drop(file);
+        PartialDownload::finish(destination)?;
+        
+        Ok(StreamedFile {
+            path: destination.to_path_buf(),
+            bytes: downloaded - resumed_at,
+            digest: hasher.map(Hasher::finalize),
+        })
+    }
+    
+    // Moves a .part file the sidecar records as complete into place without
+    // asking the server for more. Returns None, after discarding it, if the
+    // file on disk doesn't have the recorded size.
+    async fn finish_complete_part<F>(
+        &self,
+        destination: &Path,
+        partial: &PartialDownload,
+        algorithm: Option<HashAlgorithm>,
+        progress_callback: &mut F,
+    ) -> Result<Option<StreamedFile>>
+    where
+        F: FnMut(u64, u64),
+    {
+        let part_path = PartialDownload::part_path(destination);
+        let size = tokio::fs::metadata(&part_path).await?.len();
+        
+        if partial.size != Some(size) {
+            debug!("Discarding {:?}: sidecar and file size disagree", part_path);
+            PartialDownload::discard(destination)?;
+            return Ok(None);
+        }
+        
+        info!("{:?} was already fully downloaded", destination);
+        let digest = match algorithm {
+            Some(algorithm) => Some(checksum::hash_file(&part_path, algorithm).await?),
+            None => None,
+        };
+        
+        progress_callback(size, size);
+        PartialDownload::finish(destination)?;
+        
+        Ok(Some(StreamedFile {
+            path: destination.to_path_buf(),
+            bytes: 0,
+            digest,
+        }))
     }
 }
 
-#[derive(Debug, Clone)]
+struct StreamedFile {
+    path: PathBuf,
+    // Bytes received by this request, excluding any resumed prefix.
+    bytes: u64,
+    digest: Option<String>,
+}
+
+// First byte of a `Content-Range: bytes <first>-<last>/<total>` response.
+pub(crate) fn content_range_start(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    let value = headers.get(reqwest::header::CONTENT_RANGE)?.to_str().ok()?;
+    let (first, _) = value.trim().strip_prefix("bytes ")?.split_once('-')?;
+    first.trim().parse().ok()
+}
+
+// Makes the bytes written so far durable and records them in the sidecar.
+async fn checkpoint(
+    file: &mut File,
+    partial: &mut PartialDownload,
+    destination: &Path,
+    downloaded: u64,
+) -> Result<()> {
+    file.flush().await?;
+    file.sync_data().await?;
+    partial.mark_completed(0, downloaded);
+    partial.save(destination)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PatchInfo {
     pub filename: String,
     pub checksum: Option<String>,
     pub size: Option<u64>,
+    // The fields below are only set by manifest patch lists.
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub target: Option<PatchTarget>,
+    #[serde(default)]
+    pub hash_algorithm: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub min_patcher_version: Option<String>,
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    #[serde(default)]
+    pub optional: bool,
+}
+
+impl PatchInfo {
+    // Name other patches use to refer to this one in their dependencies.
+    pub fn key(&self) -> &str {
+        self.id.as_deref().unwrap_or(&self.filename)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_RANGE};
+    
+    fn content_range(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_RANGE, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+    
+    #[test]
+    fn content_range_start_reads_the_first_byte() {
+        assert_eq!(content_range_start(&content_range("bytes 1024-2047/4096")), Some(1024));
+        assert_eq!(content_range_start(&content_range("bytes 0-99/*")), Some(0));
+    }
+    
+    #[test]
+    fn content_range_start_rejects_missing_or_odd_headers() {
+        assert_eq!(content_range_start(&HeaderMap::new()), None);
+        assert_eq!(content_range_start(&content_range("bytes */4096")), None);
+        assert_eq!(content_range_start(&content_range("items 0-1/2")), None);
+    }
+    
+    #[tokio::test]
+    async fn complete_part_is_finished_without_a_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.app.game_directory = Some(dir.path().to_string_lossy().to_string());
+        let downloader = Downloader::new(config).unwrap();
+        
+        let destination = dir.path().join("update.thor");
+        let data = b"already downloaded".to_vec();
+        std::fs::write(PartialDownload::part_path(&destination), &data).unwrap();
+        let mut partial = PartialDownload::new(Some(data.len() as u64));
+        partial.sources.insert("http://127.0.0.1:9/update.thor".to_string(), Validators::default());
+        partial.mark_completed(0, data.len() as u64);
+        partial.save(&destination).unwrap();
+        
+        // Nothing listens on the discard port, so any request would fail.
+        let mut reported = None;
+        let streamed = downloader
+            .download_single_stream(
+                "http://127.0.0.1:9/update.thor",
+                &destination,
+                Some(HashAlgorithm::Sha256),
+                &mut |downloaded, total| reported = Some((downloaded, total)),
+            )
+            .await
+            .unwrap();
+        
+        assert_eq!(std::fs::read(&destination).unwrap(), data);
+        assert_eq!(streamed.bytes, 0);
+        assert_eq!(streamed.digest, Some(checksum::hash_file(&destination, HashAlgorithm::Sha256).await.unwrap()));
+        assert_eq!(reported, Some((data.len() as u64, data.len() as u64)));
+        assert!(PartialDownload::load(&destination).is_none());
+    }
 }
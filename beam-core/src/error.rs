@@ -14,12 +14,22 @@ pub enum Error {
     #[error("Config error: {0}")]
     Config(#[from] serde_yaml::Error),
     
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    
     #[error("Self update error: {0}")]
     SelfUpdate(String),
     
     #[error("Download failed: {0}")]
     DownloadFailed(String),
     
+    #[error("HTTP {status} from {url}")]
+    Http { url: String, status: u16 },
+    
+    // The connection ended before the whole body arrived.
+    #[error("Download interrupted: {0}")]
+    Interrupted(String),
+    
     #[error("Patch failed: {0}")]
     PatchFailed(String),
     
@@ -31,6 +41,15 @@ pub enum Error {
     
     #[error("Invalid configuration: {0}")]
     InvalidConfig(String),
+    
+    #[error("Not found: {0}")]
+    NotFound(String),
+    
+    #[error("Signature verification failed: {0}")]
+    Signature(String),
+    
+    #[error("Patching cancelled")]
+    Cancelled,
 }
 
 impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
@@ -39,4 +58,35 @@ impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
     }
 }
 
+impl Error {
+    // Whether the same request may succeed if simply tried again. Missing
+    // files, checksum mismatches and local I/O problems won't.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Network(e) => {
+                if let Some(status) = e.status() {
+                    return is_retryable_status(status.as_u16());
+                }
+                e.is_timeout() || e.is_connect() || e.is_request() || e.is_body()
+            }
+            Error::Http { status, .. } => is_retryable_status(*status),
+            Error::Interrupted(_) => true,
+            Error::Io(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::UnexpectedEof
+                    | std::io::ErrorKind::Interrupted
+            ),
+            _ => false,
+        }
+    }
+}
+
+fn is_retryable_status(status: u16) -> bool {
+    status >= 500 || status == 408 || status == 429
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,53 @@
+use crate::progress::TransferStats;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+// What a patch run is doing. Indexes refer to the patch list being applied;
+// downloads run ahead, so they may report on a later patch than the one
+// being applied. Serialized with an `event` tag for the UI.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PatchEvent {
+    PatchListFetched { count: usize, total_bytes: u64 },
+    DownloadStarted { index: usize, filename: String, size: Option<u64> },
+    DownloadProgress { index: usize, filename: String, downloaded: u64, total: u64, stats: TransferStats },
+    DownloadFinished { index: usize, filename: String },
+    // The patch archive is being opened and its contents checked.
+    Verifying { index: usize, filename: String },
+    Applying { index: usize, filename: String },
+    // `entry` counts from 1 up to `entries`.
+    ApplyingEntry { index: usize, entry: usize, entries: usize, name: String },
+    // A GRF rebuild holding `patches` patches was written.
+    Saved { target: String, patches: usize },
+    Applied { index: usize, filename: String },
+    Skipped { index: usize, filename: String, reason: String },
+    // The run stopped; the latest Applying event names the patch involved.
+    Failed { error: String },
+    // Stopped on request; patches saved before the cancel stay applied.
+    Cancelled,
+    Finished { applied: usize, skipped: usize },
+}
+
+// Publishes events to the current subscriber, if any. Clones share the
+// subscriber, so background tasks can report on behalf of the patcher.
+#[derive(Debug, Clone, Default)]
+pub struct EventSender {
+    subscriber: Arc<Mutex<Option<mpsc::UnboundedSender<PatchEvent>>>>,
+}
+
+impl EventSender {
+    // Starts a new stream, replacing any earlier subscriber. The stream ends
+    // once the patcher is dropped.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<PatchEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        *self.subscriber.lock().unwrap() = Some(tx);
+        rx
+    }
+    
+    pub fn emit(&self, event: PatchEvent) {
+        if let Some(tx) = &*self.subscriber.lock().unwrap() {
+            let _ = tx.send(event);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{Error, GameSettings, GameSettingsManager, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+const PRESETS_FILE: &str = "game_presets.yml";
+const PROFILES_DIR: &str = "profiles";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GamePreset {
+    pub name: String,
+    pub description: Option<String>,
+    pub settings: GameSettings,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GamePresets {
+    pub presets: Vec<GamePreset>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingDifference {
+    pub key: String,
+    pub left: String,
+    pub right: String,
+}
+
+pub struct GamePresetStore {
+    path: PathBuf,
+}
+
+impl GamePresetStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        GamePresetStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+    
+    // Presets live next to config.yml so they travel with the patcher install.
+    pub fn open_default() -> Result<Self> {
+        Self::open_profile(None)
+    }
+    
+    // Each profile keeps its own presets under profiles/<name>/. Without a
+    // profile the shared game_presets.yml is used.
+    pub fn open_profile(profile: Option<&str>) -> Result<Self> {
+        Self::in_dir(crate::get_executable_dir()?, profile)
+    }
+    
+    pub fn in_dir<P: AsRef<Path>>(dir: P, profile: Option<&str>) -> Result<Self> {
+        let dir = dir.as_ref();
+        match profile {
+            Some(name) => Ok(Self::new(
+                dir.join(PROFILES_DIR).join(profile_dir_name(name)?).join(PRESETS_FILE),
+            )),
+            None => Ok(Self::new(dir.join(PRESETS_FILE))),
+        }
+    }
+    
+    pub fn list_profiles() -> Result<Vec<String>> {
+        list_profiles_in(crate::get_executable_dir()?)
+    }
+    
+    pub fn load(&self) -> Result<GamePresets> {
+        if !self.path.exists() {
+            return Ok(GamePresets::default());
+        }
+        
+        let content = std::fs::read_to_string(&self.path)?;
+        Ok(serde_yaml::from_str(&content)?)
+    }
+    
+    fn save(&self, presets: &GamePresets) -> Result<()> {
+        let content = serde_yaml::to_string(presets)?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, content)?;
+        Ok(())
+    }
+    
+    pub fn list(&self) -> Result<Vec<GamePreset>> {
+        Ok(self.load()?.presets)
+    }
+    
+    pub fn get(&self, name: &str) -> Result<GamePreset> {
+        self.load()?
+            .presets
+            .into_iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::NotFound(format!("game settings preset '{}'", name)))
+    }
+    
+    pub fn save_preset(&self, preset: GamePreset) -> Result<()> {
+        if preset.name.trim().is_empty() {
+            return Err(Error::InvalidConfig("Preset name cannot be empty".to_string()));
+        }
+        
+        let mut presets = self.load()?;
+        match presets.presets.iter_mut().find(|p| p.name.eq_ignore_ascii_case(&preset.name)) {
+            Some(existing) => *existing = preset,
+            None => presets.presets.push(preset),
+        }
+        
+        self.save(&presets)
+    }
+    
+    pub fn save_current(
+        &self,
+        manager: &GameSettingsManager,
+        name: &str,
+        description: Option<String>,
+    ) -> Result<GamePreset> {
+        let preset = GamePreset {
+            name: name.trim().to_string(),
+            description,
+            settings: manager.load_settings()?,
+        };
+        
+        self.save_preset(preset.clone())?;
+        info!("Saved current game settings as preset: {}", preset.name);
+        
+        Ok(preset)
+    }
+    
+    pub fn apply(&self, manager: &GameSettingsManager, name: &str) -> Result<()> {
+        let preset = self.get(name)?;
+        manager.apply_settings(&preset.settings)?;
+        info!("Applied game settings preset: {}", preset.name);
+        Ok(())
+    }
+    
+    pub fn delete(&self, name: &str) -> Result<bool> {
+        let mut presets = self.load()?;
+        let before = presets.presets.len();
+        presets.presets.retain(|p| !p.name.eq_ignore_ascii_case(name));
+        
+        if presets.presets.len() == before {
+            return Ok(false);
+        }
+        
+        self.save(&presets)?;
+        Ok(true)
+    }
+    
+    pub fn diff(&self, left: &str, right: &str) -> Result<Vec<SettingDifference>> {
+        let left = self.get(left)?;
+        let right = self.get(right)?;
+        Ok(diff_settings(&left.settings, &right.settings))
+    }
+}
+
+pub fn diff_settings(left: &GameSettings, right: &GameSettings) -> Vec<SettingDifference> {
+    left.ini_values()
+        .into_iter()
+        .zip(right.ini_values())
+        .filter(|((_, l), (_, r))| l != r)
+        .map(|((key, l), (_, r))| SettingDifference {
+            key: key.to_string(),
+            left: l,
+            right: r,
+        })
+        .collect()
+}
+
+pub fn list_profiles_in<P: AsRef<Path>>(dir: P) -> Result<Vec<String>> {
+    let profiles_dir = dir.as_ref().join(PROFILES_DIR);
+    if !profiles_dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut profiles = Vec::new();
+    for entry in std::fs::read_dir(&profiles_dir)? {
+        let entry = entry?;
+        if entry.path().join(PRESETS_FILE).is_file() {
+            profiles.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    
+    profiles.sort();
+    Ok(profiles)
+}
+
+// Profile names become directory names, so keep them to a safe character set.
+fn profile_dir_name(name: &str) -> Result<&str> {
+    let name = name.trim();
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    
+    if !valid {
+        return Err(Error::InvalidConfig(format!(
+            "Invalid profile name '{}': use letters, digits, '-' or '_'",
+            name
+        )));
+    }
+    
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn preset(name: &str, width: u32) -> GamePreset {
+        GamePreset {
+            name: name.to_string(),
+            description: None,
+            settings: GameSettings {
+                resolution_width: width,
+                ..GameSettings::default()
+            },
+        }
+    }
+    
+    #[test]
+    fn missing_preset_is_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = GamePresetStore::in_dir(dir.path(), None).unwrap();
+        
+        assert!(matches!(store.get("streaming"), Err(Error::NotFound(_))));
+        assert!(matches!(store.diff("streaming", "max"), Err(Error::NotFound(_))));
+    }
+    
+    #[test]
+    fn save_replaces_preset_with_same_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = GamePresetStore::in_dir(dir.path(), None).unwrap();
+        
+        store.save_preset(preset("Streaming", 1280)).unwrap();
+        store.save_preset(preset("streaming", 1600)).unwrap();
+        
+        let presets = store.list().unwrap();
+        assert_eq!(presets.len(), 1);
+        assert_eq!(presets[0].settings.resolution_width, 1600);
+        
+        let diff = diff_settings(&preset("a", 1280).settings, &preset("b", 1920).settings);
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].key, "WIDTH");
+        
+        assert!(store.delete("STREAMING").unwrap());
+        assert!(!store.delete("streaming").unwrap());
+    }
+    
+    #[test]
+    fn profiles_keep_separate_presets() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = GamePresetStore::in_dir(dir.path(), None).unwrap();
+        let alice = GamePresetStore::in_dir(dir.path(), Some("alice")).unwrap();
+        let bob = GamePresetStore::in_dir(dir.path(), Some("bob")).unwrap();
+        
+        shared.save_preset(preset("max quality", 2560)).unwrap();
+        alice.save_preset(preset("streaming", 1280)).unwrap();
+        bob.save_preset(preset("low-end laptop", 1024)).unwrap();
+        
+        assert!(alice.get("streaming").is_ok());
+        assert!(matches!(bob.get("streaming"), Err(Error::NotFound(_))));
+        assert!(matches!(shared.get("streaming"), Err(Error::NotFound(_))));
+        assert_eq!(list_profiles_in(dir.path()).unwrap(), vec!["alice", "bob"]);
+    }
+    
+    #[test]
+    fn rejects_unsafe_profile_names() {
+        let dir = tempfile::tempdir().unwrap();
+        
+        for name in ["", "  ", "../other", "a/b", "a b"] {
+            assert!(matches!(
+                GamePresetStore::in_dir(dir.path(), Some(name)),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+}
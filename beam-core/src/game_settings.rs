@@ -1,18 +1,59 @@
-use crate::Result;
+use crate::lua_table::{LuaDocument, LuaKey, LuaValue};
+use crate::{Error, Result, WineRegistry};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
 use tracing::{info, warn};
 
+const SETTING_SECTION: &str = "[SETTING]";
+const RAGNAROK_REGISTRY_KEY: &str = r"Software\Gravity Soft\Ragnarok";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowMode {
+    Windowed,
+    Fullscreen,
+    Borderless,
+}
+
+impl WindowMode {
+    fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0 => Some(WindowMode::Windowed),
+            1 => Some(WindowMode::Fullscreen),
+            2 => Some(WindowMode::Borderless),
+            _ => None,
+        }
+    }
+    
+    fn code(self) -> u32 {
+        match self {
+            WindowMode::Windowed => 0,
+            WindowMode::Fullscreen => 1,
+            WindowMode::Borderless => 2,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameSettings {
     pub resolution_width: u32,
     pub resolution_height: u32,
-    pub fullscreen: bool,
+    pub color_depth: u32,
+    pub window_mode: WindowMode,
+    pub vsync: bool,
     pub sound_enabled: bool,
+    pub sound_volume: u32,
     pub bgm_enabled: bool,
+    pub bgm_volume: u32,
+    pub digital_sound: bool,
     pub mouse_freedom: bool,
-    pub vsync: bool,
+    pub sprite_quality: u32,
+    pub texture_quality: u32,
+    pub fog: bool,
+    pub lightmap: bool,
+    pub trilinear_filter: bool,
+    pub effects: bool,
 }
 
 impl Default for GameSettings {
@@ -20,29 +61,207 @@ impl Default for GameSettings {
         GameSettings {
             resolution_width: 1920,
             resolution_height: 1080,
-            fullscreen: false,
+            color_depth: 32,
+            window_mode: WindowMode::Windowed,
+            vsync: true,
             sound_enabled: true,
+            sound_volume: 100,
             bgm_enabled: true,
+            bgm_volume: 100,
+            digital_sound: false,
             mouse_freedom: true,
-            vsync: true,
+            sprite_quality: 3,
+            texture_quality: 3,
+            fog: true,
+            lightmap: true,
+            trilinear_filter: false,
+            effects: true,
+        }
+    }
+}
+
+impl GameSettings {
+    pub fn is_fullscreen(&self) -> bool {
+        self.window_mode == WindowMode::Fullscreen
+    }
+    
+    // DATA.INI keys in the order they are written to a fresh file.
+    pub fn ini_values(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("WIDTH", self.resolution_width.to_string()),
+            ("HEIGHT", self.resolution_height.to_string()),
+            ("BITPERPIXEL", self.color_depth.to_string()),
+            ("SCREENMODE", self.window_mode.code().to_string()),
+            ("ISFULLSCREENMODE", bool_flag(self.is_fullscreen()).to_string()),
+            ("ISVSYNC", bool_flag(self.vsync).to_string()),
+            ("SOUNDON", bool_flag(self.sound_enabled).to_string()),
+            ("SOUNDVOLUME", self.sound_volume.min(100).to_string()),
+            ("BGMON", bool_flag(self.bgm_enabled).to_string()),
+            ("BGMVOLUME", self.bgm_volume.min(100).to_string()),
+            ("DIGITAL", bool_flag(self.digital_sound).to_string()),
+            ("MOUSEEXCLUSIVE", bool_flag(!self.mouse_freedom).to_string()),
+            ("SPRITE", self.sprite_quality.min(3).to_string()),
+            ("TEXTURE", self.texture_quality.min(3).to_string()),
+            ("ISFOGON", bool_flag(self.fog).to_string()),
+            ("ISLIGHTMAP", bool_flag(self.lightmap).to_string()),
+            ("TRILINEARFILTER", bool_flag(self.trilinear_filter).to_string()),
+            ("ISEFFECTON", bool_flag(self.effects).to_string()),
+        ]
+    }
+    
+    // Returns false for keys we don't manage or values we can't interpret,
+    // leaving the current value in place.
+    pub fn set_ini_value(&mut self, key: &str, value: &str) -> bool {
+        let value = value.trim();
+        let number = value.parse::<u32>().ok();
+        
+        match (key.trim().to_ascii_uppercase().as_str(), number) {
+            ("WIDTH", Some(n)) => self.resolution_width = n,
+            ("HEIGHT", Some(n)) => self.resolution_height = n,
+            ("BITPERPIXEL", Some(n)) => self.color_depth = n,
+            ("SCREENMODE", Some(n)) => match WindowMode::from_code(n) {
+                Some(mode) => self.window_mode = mode,
+                None => return false,
+            },
+            ("ISFULLSCREENMODE", Some(1)) => self.window_mode = WindowMode::Fullscreen,
+            ("ISFULLSCREENMODE", Some(0)) => {
+                if self.window_mode == WindowMode::Fullscreen {
+                    self.window_mode = WindowMode::Windowed;
+                }
+            }
+            ("ISVSYNC", Some(n)) => self.vsync = n != 0,
+            ("SOUNDON", Some(n)) => self.sound_enabled = n != 0,
+            ("SOUNDVOLUME", Some(n)) => self.sound_volume = n.min(100),
+            ("BGMON", Some(n)) => self.bgm_enabled = n != 0,
+            ("BGMVOLUME", Some(n)) => self.bgm_volume = n.min(100),
+            ("DIGITAL", Some(n)) => self.digital_sound = n != 0,
+            ("MOUSEEXCLUSIVE", Some(n)) => self.mouse_freedom = n == 0,
+            ("SPRITE", Some(n)) => self.sprite_quality = n.min(3),
+            ("TEXTURE", Some(n)) => self.texture_quality = n.min(3),
+            ("ISFOGON", Some(n)) => self.fog = n != 0,
+            ("ISLIGHTMAP", Some(n)) => self.lightmap = n != 0,
+            ("TRILINEARFILTER", Some(n)) => self.trilinear_filter = n != 0,
+            ("ISEFFECTON", Some(n)) => self.effects = n != 0,
+            _ => return false,
+        }
+        
+        true
+    }
+    
+    // Registry value names used by opensetup under Software\Gravity Soft\Ragnarok.
+    pub fn registry_values(&self) -> Vec<(&'static str, u32)> {
+        vec![
+            ("Width", self.resolution_width),
+            ("Height", self.resolution_height),
+            ("BitPerPixel", self.color_depth),
+            ("ScreenMode", self.window_mode.code()),
+            ("Fullscreen", bool_flag(self.is_fullscreen())),
+            ("VSync", bool_flag(self.vsync)),
+            ("SoundOn", bool_flag(self.sound_enabled)),
+            ("SoundVolume", self.sound_volume.min(100)),
+            ("BGMOn", bool_flag(self.bgm_enabled)),
+            ("BGMVolume", self.bgm_volume.min(100)),
+            ("Digital", bool_flag(self.digital_sound)),
+            ("MouseExclusive", bool_flag(!self.mouse_freedom)),
+            ("SpriteMode", self.sprite_quality.min(3)),
+            ("TextureMode", self.texture_quality.min(3)),
+            ("Fog", bool_flag(self.fog)),
+            ("Lightmap", bool_flag(self.lightmap)),
+            ("TrilinearFilter", bool_flag(self.trilinear_filter)),
+            ("Effect", bool_flag(self.effects)),
+        ]
+    }
+    
+    pub fn set_registry_value(&mut self, name: &str, value: u32) -> bool {
+        match name {
+            "Width" => self.resolution_width = value,
+            "Height" => self.resolution_height = value,
+            "BitPerPixel" => self.color_depth = value,
+            "ScreenMode" => match WindowMode::from_code(value) {
+                Some(mode) => self.window_mode = mode,
+                None => return false,
+            },
+            "Fullscreen" => {
+                if value == 1 {
+                    self.window_mode = WindowMode::Fullscreen;
+                } else if self.window_mode == WindowMode::Fullscreen {
+                    self.window_mode = WindowMode::Windowed;
+                }
+            }
+            "VSync" => self.vsync = value != 0,
+            "SoundOn" => self.sound_enabled = value != 0,
+            "SoundVolume" => self.sound_volume = value.min(100),
+            "BGMOn" => self.bgm_enabled = value != 0,
+            "BGMVolume" => self.bgm_volume = value.min(100),
+            "Digital" => self.digital_sound = value != 0,
+            "MouseExclusive" => self.mouse_freedom = value == 0,
+            "SpriteMode" => self.sprite_quality = value.min(3),
+            "TextureMode" => self.texture_quality = value.min(3),
+            "Fog" => self.fog = value != 0,
+            "Lightmap" => self.lightmap = value != 0,
+            "TrilinearFilter" => self.trilinear_filter = value != 0,
+            "Effect" => self.effects = value != 0,
+            _ => return false,
+        }
+        
+        true
+    }
+}
+
+fn bool_flag(value: bool) -> u32 {
+    u32::from(value)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientLuaFile {
+    OptionInfo,
+    UserKeys,
+    ChatWindow,
+}
+
+impl ClientLuaFile {
+    pub fn relative_path(self) -> &'static str {
+        match self {
+            ClientLuaFile::OptionInfo => "savedata/OptionInfo.lua",
+            ClientLuaFile::UserKeys => "savedata/UserKeys_s.lua",
+            ClientLuaFile::ChatWindow => "savedata/ChatWndInfo_T.lua",
         }
     }
 }
 
 pub struct GameSettingsManager {
     game_directory: PathBuf,
+    wine_prefix: Option<PathBuf>,
 }
 
 impl GameSettingsManager {
     pub fn new<P: AsRef<Path>>(game_directory: P) -> Self {
         GameSettingsManager {
             game_directory: game_directory.as_ref().to_path_buf(),
+            wine_prefix: None,
         }
     }
     
+    pub fn with_wine_prefix<P: AsRef<Path>>(mut self, wine_prefix: Option<P>) -> Self {
+        self.wine_prefix = wine_prefix.map(|p| p.as_ref().to_path_buf());
+        self
+    }
+    
     pub fn apply_settings(&self, settings: &GameSettings) -> Result<()> {
         info!("Applying game settings to {:?}", self.game_directory);
         
+        // A running wineserver would write its copy of the registry back over
+        // ours; refuse before touching anything.
+        if let Some(prefix) = &self.wine_prefix {
+            if WineRegistry::wineserver_running(prefix) {
+                return Err(Error::InvalidConfig(format!(
+                    "Wine is still running for {:?}, close the game before changing settings",
+                    prefix
+                )));
+            }
+        }
+        
         let data_ini_path = self.game_directory.join("DATA.INI");
         
         if data_ini_path.exists() {
@@ -51,7 +270,6 @@ impl GameSettingsManager {
             self.create_data_ini(&data_ini_path, settings)?;
         }
         
-        #[cfg(target_os = "windows")]
         self.apply_registry_settings(settings)?;
         
         Ok(())
@@ -59,17 +277,30 @@ impl GameSettingsManager {
     
     fn modify_data_ini(&self, path: &Path, settings: &GameSettings) -> Result<()> {
         let content = fs::read_to_string(path)?;
+        let line_ending = if content.contains("\r\n") { "\r\n" } else { "\n" };
         let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
         
-        self.update_ini_value(&mut lines, "WIDTH", &settings.resolution_width.to_string());
-        self.update_ini_value(&mut lines, "HEIGHT", &settings.resolution_height.to_string());
-        self.update_ini_value(&mut lines, "SCREENMODE", if settings.fullscreen { "1" } else { "0" });
-        self.update_ini_value(&mut lines, "SOUNDVOLUME", if settings.sound_enabled { "100" } else { "0" });
-        self.update_ini_value(&mut lines, "BGMVOLUME", if settings.bgm_enabled { "100" } else { "0" });
-        self.update_ini_value(&mut lines, "ISFULLSCREENMODE", if settings.fullscreen { "1" } else { "0" });
-        self.update_ini_value(&mut lines, "MOUSEEXCLUSIVE", if settings.mouse_freedom { "0" } else { "1" });
+        // Only touch keys whose value actually changes, so values we can't
+        // interpret survive a load/apply round trip.
+        let current = self.load_from_data_ini(path)?.ini_values();
+        
+        for (key, value) in settings.ini_values() {
+            let has_key = lines.iter().any(|l| {
+                l.split_once('=').is_some_and(|(k, _)| k.trim().eq_ignore_ascii_case(key))
+            });
+            let unchanged = current.iter().any(|(k, v)| *k == key && *v == value);
+            
+            if has_key && unchanged {
+                continue;
+            }
+            
+            self.update_ini_value(&mut lines, key, &value);
+        }
         
-        let new_content = lines.join("\n");
+        let mut new_content = lines.join(line_ending);
+        if content.ends_with('\n') {
+            new_content.push_str(line_ending);
+        }
         fs::write(path, new_content)?;
         
         info!("DATA.INI updated successfully");
@@ -77,43 +308,49 @@ impl GameSettingsManager {
     }
     
     fn create_data_ini(&self, path: &Path, settings: &GameSettings) -> Result<()> {
-        let content = format!(
-            "[SETTING]\n\
-             WIDTH={}\n\
-             HEIGHT={}\n\
-             SCREENMODE={}\n\
-             ISFULLSCREENMODE={}\n\
-             SOUNDVOLUME={}\n\
-             BGMVOLUME={}\n\
-             MOUSEEXCLUSIVE={}\n\
-             SPRITE=3\n\
-             TEXTURE=3\n\
-             DIGITAL=0\n",
-            settings.resolution_width,
-            settings.resolution_height,
-            if settings.fullscreen { 1 } else { 0 },
-            if settings.fullscreen { 1 } else { 0 },
-            if settings.sound_enabled { 100 } else { 0 },
-            if settings.bgm_enabled { 100 } else { 0 },
-            if settings.mouse_freedom { 0 } else { 1 }
-        );
+        let mut content = format!("{}\n", SETTING_SECTION);
+        for (key, value) in settings.ini_values() {
+            content.push_str(&format!("{}={}\n", key, value));
+        }
         
         fs::write(path, content)?;
         info!("DATA.INI created successfully");
         Ok(())
     }
     
+    // Rewrites only the value of `key`, keeping the original key spelling and
+    // every other line as-is. Missing keys are appended to the [SETTING]
+    // section so they don't end up inside an unrelated section.
     fn update_ini_value(&self, lines: &mut Vec<String>, key: &str, value: &str) {
-        let key_prefix = format!("{}=", key);
-        
         for line in lines.iter_mut() {
-            if line.starts_with(&key_prefix) {
-                *line = format!("{}={}", key, value);
-                return;
+            if let Some((existing_key, _)) = line.split_once('=') {
+                if existing_key.trim().eq_ignore_ascii_case(key) {
+                    *line = format!("{}={}", existing_key, value);
+                    return;
+                }
             }
         }
         
-        lines.push(format!("{}={}", key, value));
+        let section_start = lines
+            .iter()
+            .position(|l| l.trim().eq_ignore_ascii_case(SETTING_SECTION));
+        
+        match section_start {
+            Some(start) => {
+                let mut insert_at = start + 1;
+                for (idx, line) in lines.iter().enumerate().skip(start + 1) {
+                    let trimmed = line.trim();
+                    if trimmed.starts_with('[') {
+                        break;
+                    }
+                    if !trimmed.is_empty() {
+                        insert_at = idx + 1;
+                    }
+                }
+                lines.insert(insert_at, format!("{}={}", key, value));
+            }
+            None => lines.push(format!("{}={}", key, value)),
+        }
     }
     
     #[cfg(target_os = "windows")]
@@ -123,13 +360,13 @@ impl GameSettingsManager {
         
         let hkcu = RegKey::predef(HKEY_CURRENT_USER);
         
-        match hkcu.create_subkey(r"Software\Gravity Soft\Ragnarok") {
+        match hkcu.create_subkey(RAGNAROK_REGISTRY_KEY) {
             Ok((key, _)) => {
-                let _ = key.set_value("Width", &settings.resolution_width);
-                let _ = key.set_value("Height", &settings.resolution_height);
-                let _ = key.set_value("Fullscreen", &(if settings.fullscreen { 1u32 } else { 0u32 }));
-                let _ = key.set_value("SoundVolume", &(if settings.sound_enabled { 100u32 } else { 0u32 }));
-                let _ = key.set_value("BGMVolume", &(if settings.bgm_enabled { 100u32 } else { 0u32 }));
+                for (name, value) in settings.registry_values() {
+                    if let Err(e) = key.set_value(name, &value) {
+                        warn!("Failed to write registry value {}: {}", name, e);
+                    }
+                }
                 
                 info!("Registry settings applied successfully");
             }
@@ -142,10 +379,40 @@ impl GameSettingsManager {
     }
     
     #[cfg(not(target_os = "windows"))]
-    fn apply_registry_settings(&self, _settings: &GameSettings) -> Result<()> {
+    fn apply_registry_settings(&self, settings: &GameSettings) -> Result<()> {
+        let Some(user_reg_path) = self.wine_user_reg() else {
+            return Ok(());
+        };
+        
+        let mut registry = match WineRegistry::open(&user_reg_path) {
+            Ok(registry) => registry,
+            Err(e) => {
+                warn!("Failed to open Wine registry {:?}: {}", user_reg_path, e);
+                return Ok(());
+            }
+        };
+        
+        for (name, value) in settings.registry_values() {
+            registry.set_dword(RAGNAROK_REGISTRY_KEY, name, value);
+        }
+        
+        registry.save()?;
+        info!("Wine registry settings applied to {:?}", user_reg_path);
+        
         Ok(())
     }
     
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    fn wine_user_reg(&self) -> Option<PathBuf> {
+        let path = WineRegistry::user_reg_path(self.wine_prefix.as_ref()?);
+        if path.exists() {
+            Some(path)
+        } else {
+            warn!("Wine prefix configured but {:?} does not exist", path);
+            None
+        }
+    }
+    
     pub fn load_settings(&self) -> Result<GameSettings> {
         let data_ini_path = self.game_directory.join("DATA.INI");
         
@@ -154,7 +421,7 @@ impl GameSettingsManager {
         }
         
         let setup_exe_path = self.game_directory.join("opensetup.exe");
-        if setup_exe_path.exists() {
+        if setup_exe_path.exists() || self.wine_prefix.is_some() {
             info!("DATA.INI not found, attempting to read from opensetup.exe");
             return self.load_from_setup_exe(&setup_exe_path);
         }
@@ -168,23 +435,7 @@ impl GameSettingsManager {
         
         for line in content.lines() {
             if let Some((key, value)) = line.split_once('=') {
-                match key.trim() {
-                    "WIDTH" => settings.resolution_width = value.trim().parse().unwrap_or(1920),
-                    "HEIGHT" => settings.resolution_height = value.trim().parse().unwrap_or(1080),
-                    "SCREENMODE" | "ISFULLSCREENMODE" => {
-                        settings.fullscreen = value.trim() == "1";
-                    }
-                    "SOUNDVOLUME" => {
-                        settings.sound_enabled = value.trim().parse::<u32>().unwrap_or(0) > 0;
-                    }
-                    "BGMVOLUME" => {
-                        settings.bgm_enabled = value.trim().parse::<u32>().unwrap_or(0) > 0;
-                    }
-                    "MOUSEEXCLUSIVE" => {
-                        settings.mouse_freedom = value.trim() == "0";
-                    }
-                    _ => {}
-                }
+                settings.set_ini_value(key, value);
             }
         }
         
@@ -200,21 +451,11 @@ impl GameSettingsManager {
             let hkcu = RegKey::predef(HKEY_CURRENT_USER);
             let mut settings = GameSettings::default();
             
-            if let Ok(key) = hkcu.open_subkey(r"Software\Gravity Soft\Ragnarok") {
-                if let Ok(width) = key.get_value::<u32, _>("Width") {
-                    settings.resolution_width = width;
-                }
-                if let Ok(height) = key.get_value::<u32, _>("Height") {
-                    settings.resolution_height = height;
-                }
-                if let Ok(fullscreen) = key.get_value::<u32, _>("Fullscreen") {
-                    settings.fullscreen = fullscreen == 1;
-                }
-                if let Ok(sound) = key.get_value::<u32, _>("SoundVolume") {
-                    settings.sound_enabled = sound > 0;
-                }
-                if let Ok(bgm) = key.get_value::<u32, _>("BGMVolume") {
-                    settings.bgm_enabled = bgm > 0;
+            if let Ok(key) = hkcu.open_subkey(RAGNAROK_REGISTRY_KEY) {
+                for (name, _) in GameSettings::default().registry_values() {
+                    if let Ok(value) = key.get_value::<u32, _>(name) {
+                        settings.set_registry_value(name, value);
+                    }
                 }
                 
                 info!("Loaded settings from Windows Registry (opensetup.exe fallback)");
@@ -227,7 +468,82 @@ impl GameSettingsManager {
         
         #[cfg(not(target_os = "windows"))]
         {
-            Ok(GameSettings::default())
+            let mut settings = GameSettings::default();
+            
+            let Some(user_reg_path) = self.wine_user_reg() else {
+                return Ok(settings);
+            };
+            
+            match WineRegistry::open(&user_reg_path) {
+                Ok(registry) => {
+                    for (name, _) in GameSettings::default().registry_values() {
+                        if let Some(value) = registry.get_dword(RAGNAROK_REGISTRY_KEY, name) {
+                            settings.set_registry_value(name, value);
+                        }
+                    }
+                    
+                    info!("Loaded settings from Wine registry {:?}", user_reg_path);
+                }
+                Err(e) => {
+                    warn!("Could not read Wine registry settings, using defaults: {}", e);
+                }
+            }
+            
+            Ok(settings)
         }
     }
+    
+    pub fn client_lua_path(&self, file: ClientLuaFile) -> PathBuf {
+        self.game_directory.join(file.relative_path())
+    }
+    
+    pub fn load_client_lua(&self, file: ClientLuaFile) -> Result<LuaDocument> {
+        let path = self.client_lua_path(file);
+        
+        if !path.exists() {
+            return Ok(LuaDocument::default());
+        }
+        
+        let data = fs::read(&path)?;
+        LuaDocument::parse_bytes(&data)
+    }
+    
+    pub fn update_client_lua(
+        &self,
+        file: ClientLuaFile,
+        updates: &[(Vec<LuaKey>, LuaValue)],
+    ) -> Result<()> {
+        let mut document = self.load_client_lua(file)?;
+        
+        for (path, value) in updates {
+            document.set(path, value.clone())?;
+        }
+        
+        let path = self.client_lua_path(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        
+        let tmp_path = path.with_extension("lua.tmp");
+        fs::write(&tmp_path, document.to_bytes()?)?;
+        fs::rename(&tmp_path, &path)?;
+        
+        info!("Updated {} ({} values)", file.relative_path(), updates.len());
+        Ok(())
+    }
+    
+    // Moves the file aside so the client regenerates its defaults on next start.
+    pub fn reset_client_lua(&self, file: ClientLuaFile) -> Result<Option<PathBuf>> {
+        let path = self.client_lua_path(file);
+        
+        if !path.exists() {
+            return Ok(None);
+        }
+        
+        let backup_path = path.with_extension("lua.bak");
+        fs::rename(&path, &backup_path)?;
+        
+        info!("Reset {}, previous file kept at {:?}", file.relative_path(), backup_path);
+        Ok(Some(backup_path))
+    }
 }
@@ -1,28 +1,65 @@
 pub mod config;
 pub mod downloader;
+pub mod checksum;
 pub mod patcher;
+pub mod events;
+pub mod progress;
+pub mod plan;
+pub mod change_log;
+pub mod control;
+pub mod patch_journal;
+pub mod patch_state;
+pub mod patch_manifest;
+pub mod signing;
 pub mod updater;
 pub mod sso;
 pub mod error;
 pub mod verifier;
 pub mod parallel_downloader;
+pub mod mirror_health;
+pub mod retry;
+pub mod rate_limit;
+pub mod session;
+pub mod partial_download;
 pub mod game_settings;
+pub mod game_presets;
+pub mod wine_registry;
+pub mod lua_table;
+pub mod client_info;
 pub mod server_checker;
 pub mod client_checker;
 
-pub use config::Config;
+pub use config::{BandwidthConfig, Config};
 pub use downloader::{Downloader, PatchInfo};
-pub use patcher::Patcher;
+pub use checksum::HashAlgorithm;
+pub use patcher::{Patcher, RecoveryOutcome};
+pub use events::PatchEvent;
+pub use progress::TransferStats;
+pub use plan::{FileChange, PatchPlan, PlannedPatch, TargetPlan};
+pub use change_log::{ChangeKind, ChangeLog, ChangeLogQuery, EntryChange, PatchChanges};
+pub use control::{PatchControl, RunState};
+pub use patch_state::{AppliedPatch, PatchState};
+pub use patch_manifest::{PatchManifest, PatchTarget};
+pub use signing::SignatureVerifier;
 pub use updater::Updater;
 pub use sso::SsoClient;
 pub use error::{Error, Result};
-pub use verifier::{Verifier, VerificationResult};
-pub use parallel_downloader::ParallelDownloader;
-pub use game_settings::{GameSettings, GameSettingsManager};
+pub use verifier::{RepairFailure, RepairResult, Verifier, VerificationResult};
+pub use parallel_downloader::{ParallelDownloader, RemoteFileInfo};
+pub use mirror_health::{MirrorHealth, MirrorStatus};
+pub use rate_limit::RateLimiter;
+pub use session::DownloadSession;
+pub use partial_download::PartialDownload;
+pub use game_settings::{ClientLuaFile, GameSettings, GameSettingsManager, WindowMode};
+pub use game_presets::{GamePreset, GamePresetStore, SettingDifference};
+pub use wine_registry::WineRegistry;
+pub use lua_table::{LuaDocument, LuaEntry, LuaKey, LuaValue};
+pub use client_info::ClientInfo;
 pub use server_checker::{ServerChecker, ServerStatusResult};
 pub use client_checker::{ClientChecker, ClientStatusResult};
 
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 pub fn get_executable_dir() -> Result<PathBuf> {
     std::env::current_exe()?
@@ -33,3 +70,24 @@ pub fn get_executable_dir() -> Result<PathBuf> {
         )))
         .map(|p| p.to_path_buf())
 }
+
+// Writes through a temporary file that is synced before being renamed over
+// the target, so readers see either the old or the new contents.
+pub(crate) fn write_file_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_name);
+    
+    write_file_synced(&tmp_path, data)?;
+    
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// Writes `data` and waits for it to reach the disk.
+pub(crate) fn write_file_synced(path: &Path, data: &[u8]) -> Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    Ok(())
+}
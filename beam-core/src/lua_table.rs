@@ -0,0 +1,752 @@
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+// Parser and writer for the data-only Lua files the client keeps in savedata/
+// (OptionInfo.lua, UserKeys_s.lua, ...). Only `name = <constructor>`
+// statements with literal values are accepted; anything that would need a Lua
+// interpreter is rejected so we never rewrite a file we didn't fully read.
+// A parsed file is written back with only the changed values replaced, so
+// comments, `local` and the original layout survive.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LuaKey {
+    Index(i64),
+    Name(String),
+}
+
+// Tagged in JSON (`{"type": "float", "value": 1.0}`) so a whole float coming
+// back from the UI isn't read as an integer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum LuaValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Table(Vec<LuaEntry>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LuaEntry {
+    pub key: LuaKey,
+    pub value: LuaValue,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LuaDocument {
+    pub globals: Vec<LuaEntry>,
+    #[serde(skip)]
+    latin1: bool,
+    // The text this document was parsed from, if any.
+    #[serde(skip)]
+    source: Option<Source>,
+}
+
+#[derive(Debug, Clone)]
+struct Source {
+    text: Vec<char>,
+    globals: Vec<(String, Spanned)>,
+}
+
+// A parsed value and the characters of the source it was read from.
+#[derive(Debug, Clone)]
+struct Spanned {
+    value: LuaValue,
+    start: usize,
+    end: usize,
+    table: Option<TableSource>,
+}
+
+#[derive(Debug, Clone)]
+struct TableSource {
+    entries: Vec<(LuaKey, Spanned)>,
+    // Where entries added to the table go: after the last entry and its
+    // separator, or after the `{` of an empty table.
+    append_at: usize,
+    // Whether an entry added at the end needs a separator before it.
+    needs_separator: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Str(String),
+    Number(String),
+    Symbol(char),
+}
+
+impl LuaDocument {
+    pub fn parse_bytes(data: &[u8]) -> Result<Self> {
+        // Older clients write these files in the system code page; read them
+        // byte-per-char so non-UTF-8 text survives a round trip unchanged.
+        let (text, latin1) = match std::str::from_utf8(data) {
+            Ok(text) => (text.to_string(), false),
+            Err(_) => (data.iter().map(|&b| b as char).collect(), true),
+        };
+        
+        let mut document = Self::parse(&text)?;
+        document.latin1 = latin1;
+        Ok(document)
+    }
+    
+    pub fn parse(source: &str) -> Result<Self> {
+        let text: Vec<char> = source.chars().collect();
+        let tokens = tokenize(&text)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let mut spanned = Vec::new();
+        
+        while parser.peek().is_some() {
+            let name = match parser.next() {
+                Some(Token::Name(name)) if name != "local" => name,
+                Some(Token::Name(_)) => parser.expect_name()?,
+                other => return Err(lua_error(format!("expected assignment, found {:?}", other))),
+            };
+            parser.expect_symbol('=')?;
+            let value = parser.parse_value()?;
+            parser.skip_symbol(';');
+            
+            spanned.push((name, value));
+        }
+        
+        let globals = spanned
+            .iter()
+            .map(|(name, value)| LuaEntry {
+                key: LuaKey::Name(name.clone()),
+                value: value.value.clone(),
+            })
+            .collect();
+        
+        Ok(LuaDocument {
+            globals,
+            latin1: false,
+            source: Some(Source { text, globals: spanned }),
+        })
+    }
+    
+    pub fn get(&self, path: &[LuaKey]) -> Option<&LuaValue> {
+        let (first, rest) = path.split_first()?;
+        let mut value = find_entry(&self.globals, first)?;
+        
+        for key in rest {
+            match value {
+                LuaValue::Table(entries) => value = find_entry(entries, key)?,
+                _ => return None,
+            }
+        }
+        
+        Some(value)
+    }
+    
+    // Sets a value by path, creating intermediate tables as needed.
+    pub fn set(&mut self, path: &[LuaKey], value: LuaValue) -> Result<()> {
+        let (last, parents) = path
+            .split_last()
+            .ok_or_else(|| lua_error("empty path".to_string()))?;
+        
+        let mut entries = &mut self.globals;
+        for key in parents {
+            let idx = match entries.iter().position(|e| &e.key == key) {
+                Some(idx) => idx,
+                None => {
+                    entries.push(LuaEntry {
+                        key: key.clone(),
+                        value: LuaValue::Table(Vec::new()),
+                    });
+                    entries.len() - 1
+                }
+            };
+            
+            entries = match &mut entries[idx].value {
+                LuaValue::Table(children) => children,
+                _ => return Err(lua_error(format!("{:?} is not a table", key))),
+            };
+        }
+        
+        match entries.iter_mut().find(|e| &e.key == last) {
+            Some(entry) => entry.value = value,
+            None => entries.push(LuaEntry {
+                key: last.clone(),
+                value,
+            }),
+        }
+        
+        Ok(())
+    }
+    
+    pub fn to_lua(&self) -> String {
+        match &self.source {
+            Some(source) => self.rewrite(source),
+            None => self.write_fresh(),
+        }
+    }
+    
+    fn write_fresh(&self) -> String {
+        let mut out = String::new();
+        for entry in &self.globals {
+            if let LuaKey::Name(name) = &entry.key {
+                out.push_str(name);
+                out.push_str(" = ");
+                write_value(&mut out, &entry.value, 0);
+                out.push('\n');
+            }
+        }
+        out
+    }
+    
+    // The parsed text with each changed value replaced and new globals
+    // appended; everything else is copied as it was.
+    fn rewrite(&self, source: &Source) -> String {
+        let mut edits = Vec::new();
+        let mut appended = String::new();
+        
+        for entry in &self.globals {
+            let LuaKey::Name(name) = &entry.key else {
+                continue;
+            };
+            match source.globals.iter().rev().find(|(original, _)| original == name) {
+                Some((_, original)) => diff_value(original, &entry.value, 0, &mut edits),
+                None => {
+                    appended.push_str(name);
+                    appended.push_str(" = ");
+                    write_value(&mut appended, &entry.value, 0);
+                    appended.push('\n');
+                }
+            }
+        }
+        
+        let mut text = source.text.clone();
+        // Later edits first, so earlier positions stay valid.
+        edits.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+        for (start, end, replacement) in edits {
+            text.splice(start..end, replacement.chars());
+        }
+        
+        let mut out: String = text.into_iter().collect();
+        if !appended.is_empty() {
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+            out.push_str(&appended);
+        }
+        out
+    }
+    
+    // Fails if a file read as Latin-1 was given text it can't hold.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let text = self.to_lua();
+        if !self.latin1 {
+            return Ok(text.into_bytes());
+        }
+        
+        text.chars()
+            .map(|c| {
+                u8::try_from(u32::from(c)).map_err(|_| Error::InvalidConfig(format!(
+                    "'{}' can't be written to a file in the system code page",
+                    c
+                )))
+            })
+            .collect()
+    }
+}
+
+// Collects the edits that turn `original`'s source text into `value`. Tables
+// are compared entry by entry, so a change deep inside one leaves the rest of
+// it, comments included, untouched.
+fn diff_value(original: &Spanned, value: &LuaValue, depth: usize, edits: &mut Vec<(usize, usize, String)>) {
+    if &original.value == value {
+        return;
+    }
+    
+    let (Some(table), LuaValue::Table(entries)) = (&original.table, value) else {
+        let mut replacement = String::new();
+        write_value(&mut replacement, value, depth);
+        edits.push((original.start, original.end, replacement));
+        return;
+    };
+    
+    let removed = table
+        .entries
+        .iter()
+        .any(|(key, _)| !entries.iter().any(|entry| &entry.key == key));
+    if removed {
+        let mut replacement = String::new();
+        write_value(&mut replacement, value, depth);
+        edits.push((original.start, original.end, replacement));
+        return;
+    }
+    
+    let mut added = String::new();
+    for entry in entries {
+        match table.entries.iter().find(|(key, _)| key == &entry.key) {
+            Some((_, child)) => diff_value(child, &entry.value, depth + 1, edits),
+            None => {
+                added.push('\n');
+                added.push_str(&"\t".repeat(depth + 1));
+                write_entry(&mut added, entry, depth + 1);
+                added.push(',');
+            }
+        }
+    }
+    
+    if !added.is_empty() {
+        if table.needs_separator {
+            added.insert(0, ',');
+        }
+        if table.entries.is_empty() {
+            added.push('\n');
+            added.push_str(&"\t".repeat(depth));
+        }
+        edits.push((table.append_at, table.append_at, added));
+    }
+}
+
+fn find_entry<'a>(entries: &'a [LuaEntry], key: &LuaKey) -> Option<&'a LuaValue> {
+    entries.iter().find(|e| &e.key == key).map(|e| &e.value)
+}
+
+fn lua_error(message: String) -> Error {
+    Error::InvalidConfig(format!("Lua parse error: {}", message))
+}
+
+fn write_value(out: &mut String, value: &LuaValue, depth: usize) {
+    match value {
+        LuaValue::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        LuaValue::Integer(n) => out.push_str(&n.to_string()),
+        LuaValue::Float(n) => write_float(out, *n),
+        LuaValue::String(s) => write_string(out, s),
+        LuaValue::Table(entries) => {
+            if entries.is_empty() {
+                out.push_str("{}");
+                return;
+            }
+            
+            out.push_str("{\n");
+            for entry in entries {
+                out.push_str(&"\t".repeat(depth + 1));
+                write_entry(out, entry, depth + 1);
+                out.push_str(",\n");
+            }
+            out.push_str(&"\t".repeat(depth));
+            out.push('}');
+        }
+    }
+}
+
+fn write_entry(out: &mut String, entry: &LuaEntry, depth: usize) {
+    match &entry.key {
+        LuaKey::Index(n) => out.push_str(&format!("[{}]", n)),
+        LuaKey::Name(name) => {
+            out.push('[');
+            write_string(out, name);
+            out.push(']');
+        }
+    }
+    out.push_str(" = ");
+    write_value(out, &entry.value, depth);
+}
+
+// Always with a decimal point or exponent, so the value reads back as a float.
+fn write_float(out: &mut String, value: f64) {
+    let text = value.to_string();
+    out.push_str(&text);
+    if text.chars().all(|c| c.is_ascii_digit() || c == '-') {
+        out.push_str(".0");
+    }
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// Tokens with the range of characters each was read from.
+fn tokenize(chars: &[char]) -> Result<Vec<(Token, usize, usize)>> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            i += 2;
+            if chars.get(i) == Some(&'[') && chars.get(i + 1) == Some(&'[') {
+                while i < chars.len() && !(chars[i] == ']' && chars.get(i + 1) == Some(&']')) {
+                    i += 1;
+                }
+                i += 2;
+            } else {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+        } else if c == '"' || c == '\'' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                let ch = *chars.get(i).ok_or_else(|| lua_error("unterminated string".to_string()))?;
+                i += 1;
+                if ch == c {
+                    break;
+                }
+                if ch == '\\' {
+                    let escaped = *chars.get(i).ok_or_else(|| lua_error("unterminated string".to_string()))?;
+                    i += 1;
+                    value.push(match escaped {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        // \ddd, up to three decimal digits.
+                        '0'..='9' => {
+                            let mut code = escaped.to_digit(10).unwrap_or(0);
+                            for _ in 0..2 {
+                                match chars.get(i).and_then(|d| d.to_digit(10)) {
+                                    Some(digit) => {
+                                        code = code * 10 + digit;
+                                        i += 1;
+                                    }
+                                    None => break,
+                                }
+                            }
+                            char::from_u32(code).ok_or_else(|| lua_error(format!("invalid escape \\{}", code)))?
+                        }
+                        other => other,
+                    });
+                } else {
+                    value.push(ch);
+                }
+            }
+            tokens.push((Token::Str(value), start, i));
+        } else if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit() || *n == '.')) || c == '.' {
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                // The sign of a decimal exponent, as in 1e-5.
+                let hex = chars[start..i].iter().any(|&x| x == 'x' || x == 'X');
+                if !hex && matches!(chars[i], 'e' | 'E') && matches!(chars.get(i + 1), Some('-' | '+')) {
+                    i += 1;
+                }
+                i += 1;
+            }
+            tokens.push((Token::Number(chars[start..i].iter().collect()), start, i));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Name(chars[start..i].iter().collect()), start, i));
+        } else if "{}[]=,;".contains(c) {
+            i += 1;
+            tokens.push((Token::Symbol(c), start, i));
+        } else {
+            return Err(lua_error(format!("unsupported character '{}'", c)));
+        }
+    }
+    
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+    
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset).map(|(token, _, _)| token)
+    }
+    
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+    
+    // Where the next token starts; the end of the text once they run out.
+    fn next_start(&self) -> usize {
+        match self.tokens.get(self.pos) {
+            Some((_, start, _)) => *start,
+            None => self.last_end(),
+        }
+    }
+    
+    // Where the last consumed token ends.
+    fn last_end(&self) -> usize {
+        self.pos
+            .checked_sub(1)
+            .and_then(|last| self.tokens.get(last))
+            .map(|(_, _, end)| *end)
+            .unwrap_or(0)
+    }
+    
+    fn skip_symbol(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+    
+    fn expect_symbol(&mut self, symbol: char) -> Result<()> {
+        if self.skip_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(lua_error(format!("expected '{}', found {:?}", symbol, self.peek())))
+        }
+    }
+    
+    fn expect_name(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Name(name)) => Ok(name),
+            other => Err(lua_error(format!("expected name, found {:?}", other))),
+        }
+    }
+    
+    fn parse_value(&mut self) -> Result<Spanned> {
+        let start = self.next_start();
+        let value = match self.next() {
+            Some(Token::Str(s)) => LuaValue::String(s),
+            Some(Token::Number(n)) => parse_number(&n)?,
+            Some(Token::Name(name)) => match name.as_str() {
+                "true" => LuaValue::Bool(true),
+                "false" => LuaValue::Bool(false),
+                _ => return Err(lua_error(format!("unsupported expression '{}'", name))),
+            },
+            Some(Token::Symbol('{')) => return self.parse_table(start),
+            other => return Err(lua_error(format!("expected value, found {:?}", other))),
+        };
+        
+        Ok(Spanned { value, start, end: self.last_end(), table: None })
+    }
+    
+    // Parses the rest of a table whose `{` started at `start`.
+    fn parse_table(&mut self, start: usize) -> Result<Spanned> {
+        let mut entries = Vec::new();
+        let mut next_index = 1;
+        let mut needs_separator = false;
+        
+        loop {
+            if self.peek() == Some(&Token::Symbol('}')) {
+                break;
+            }
+            
+            let key = if self.skip_symbol('[') {
+                let key = match self.parse_value()?.value {
+                    LuaValue::Integer(n) => LuaKey::Index(n),
+                    LuaValue::String(s) => LuaKey::Name(s),
+                    other => return Err(lua_error(format!("unsupported table key {:?}", other))),
+                };
+                self.expect_symbol(']')?;
+                self.expect_symbol('=')?;
+                key
+            } else if matches!(self.peek(), Some(Token::Name(_)))
+                && self.peek_at(1) == Some(&Token::Symbol('='))
+            {
+                let name = self.expect_name()?;
+                self.expect_symbol('=')?;
+                LuaKey::Name(name)
+            } else {
+                let key = LuaKey::Index(next_index);
+                next_index += 1;
+                key
+            };
+            
+            let value = self.parse_value()?;
+            entries.push((key, value));
+            
+            if !self.skip_symbol(',') && !self.skip_symbol(';') {
+                needs_separator = true;
+                break;
+            }
+        }
+        
+        let append_at = self.last_end();
+        self.expect_symbol('}')?;
+        
+        let value = LuaValue::Table(
+            entries
+                .iter()
+                .map(|(key, value)| LuaEntry { key: key.clone(), value: value.value.clone() })
+                .collect(),
+        );
+        
+        Ok(Spanned {
+            value,
+            start,
+            end: self.last_end(),
+            table: Some(TableSource { entries, append_at, needs_separator }),
+        })
+    }
+}
+
+fn parse_number(text: &str) -> Result<LuaValue> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    
+    let parsed = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok().map(LuaValue::Integer)
+    } else if let Ok(n) = digits.parse::<i64>() {
+        Some(LuaValue::Integer(n))
+    } else {
+        digits.parse::<f64>().ok().map(LuaValue::Float)
+    };
+    
+    match (parsed, negative) {
+        (Some(LuaValue::Integer(n)), true) => Ok(LuaValue::Integer(-n)),
+        (Some(LuaValue::Float(n)), true) => Ok(LuaValue::Float(-n)),
+        (Some(value), _) => Ok(value),
+        (None, _) => Err(lua_error(format!("invalid number '{}'", text))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn key(name: &str) -> LuaKey {
+        LuaKey::Name(name.to_string())
+    }
+    
+    // Writes the values out without the original text and reads them back.
+    fn reparse_fresh(document: &LuaDocument) -> LuaDocument {
+        let fresh = LuaDocument { globals: document.globals.clone(), ..Default::default() };
+        LuaDocument::parse(&fresh.to_lua()).unwrap()
+    }
+    
+    #[test]
+    fn strings_with_escapes_round_trip() {
+        let source = r#"Name = "a\"b\\c\n\t\65\0""#;
+        let document = LuaDocument::parse(source).unwrap();
+        let expected = LuaValue::String("a\"b\\c\n\tA\0".to_string());
+        
+        assert_eq!(document.get(&[key("Name")]), Some(&expected));
+        assert_eq!(document.to_lua(), source);
+        assert_eq!(reparse_fresh(&document).get(&[key("Name")]), Some(&expected));
+    }
+    
+    #[test]
+    fn nested_tables_round_trip() {
+        let source = "Keys = { Battle = { [1] = 'F1', Skill = { lv = 10, 'x' } }, [\"with space\"] = true; }\n";
+        let document = LuaDocument::parse(source).unwrap();
+        
+        assert_eq!(
+            document.get(&[key("Keys"), key("Battle"), key("Skill"), key("lv")]),
+            Some(&LuaValue::Integer(10))
+        );
+        assert_eq!(
+            document.get(&[key("Keys"), key("Battle"), key("Skill"), LuaKey::Index(1)]),
+            Some(&LuaValue::String("x".to_string()))
+        );
+        assert_eq!(document.get(&[key("Keys"), key("with space")]), Some(&LuaValue::Bool(true)));
+        assert_eq!(document.to_lua(), source);
+        assert_eq!(reparse_fresh(&document).globals, document.globals);
+    }
+    
+    #[test]
+    fn negative_numbers_and_floats_keep_their_type() {
+        let source = "A = -5\nB = -1.5\nC = -0x10\nD = 1.0\nE = 2.5e-3\n";
+        let document = LuaDocument::parse(source).unwrap();
+        
+        assert_eq!(document.get(&[key("A")]), Some(&LuaValue::Integer(-5)));
+        assert_eq!(document.get(&[key("B")]), Some(&LuaValue::Float(-1.5)));
+        assert_eq!(document.get(&[key("C")]), Some(&LuaValue::Integer(-16)));
+        assert_eq!(document.get(&[key("D")]), Some(&LuaValue::Float(1.0)));
+        assert_eq!(document.get(&[key("E")]), Some(&LuaValue::Float(0.0025)));
+        assert_eq!(reparse_fresh(&document).globals, document.globals);
+    }
+    
+    #[test]
+    fn whole_floats_are_written_as_floats() {
+        let mut document = LuaDocument::default();
+        document.set(&[key("Volume")], LuaValue::Float(1.0)).unwrap();
+        document.set(&[key("Gamma")], LuaValue::Float(-2.0)).unwrap();
+        
+        let reparsed = LuaDocument::parse(&document.to_lua()).unwrap();
+        assert_eq!(reparsed.get(&[key("Volume")]), Some(&LuaValue::Float(1.0)));
+        assert_eq!(reparsed.get(&[key("Gamma")]), Some(&LuaValue::Float(-2.0)));
+    }
+    
+    #[test]
+    fn editing_keeps_comments_and_local() {
+        let source = "-- Saved by the client\nlocal OptionInfo = {\n\t-- sound\n\tbgm = 50, --[[ 0-100 ]]\n\tsound = 30\n}\nOther = 'x' -- trailing\n";
+        let mut document = LuaDocument::parse(source).unwrap();
+        
+        document.set(&[key("OptionInfo"), key("bgm")], LuaValue::Integer(80)).unwrap();
+        document.set(&[key("OptionInfo"), key("effect")], LuaValue::Bool(false)).unwrap();
+        document.set(&[key("Added")], LuaValue::Float(1.0)).unwrap();
+        let written = document.to_lua();
+        
+        assert_eq!(
+            written,
+            "-- Saved by the client\nlocal OptionInfo = {\n\t-- sound\n\tbgm = 80, --[[ 0-100 ]]\n\tsound = 30,\n\t[\"effect\"] = false,\n}\nOther = 'x' -- trailing\nAdded = 1.0\n"
+        );
+        assert_eq!(LuaDocument::parse(&written).unwrap().globals, document.globals);
+    }
+    
+    #[test]
+    fn entries_can_be_added_to_an_empty_table() {
+        let mut document = LuaDocument::parse("Keys = {} -- none yet\n").unwrap();
+        
+        document.set(&[key("Keys"), LuaKey::Index(1)], LuaValue::Integer(7)).unwrap();
+        
+        assert_eq!(document.to_lua(), "Keys = {\n\t[1] = 7,\n} -- none yet\n");
+    }
+    
+    #[test]
+    fn replacing_a_table_with_a_value_rewrites_only_that_value() {
+        let source = "A = { 1, 2 } -- keep\nB = 2\n";
+        let mut document = LuaDocument::parse(source).unwrap();
+        
+        document.set(&[key("A")], LuaValue::String("flat".to_string())).unwrap();
+        
+        assert_eq!(document.to_lua(), "A = \"flat\" -- keep\nB = 2\n");
+    }
+    
+    #[test]
+    fn floats_stay_floats_through_json() {
+        let value = LuaValue::Table(vec![
+            LuaEntry { key: LuaKey::Index(1), value: LuaValue::Float(1.0) },
+            LuaEntry { key: key("n"), value: LuaValue::Integer(1) },
+        ]);
+        
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(serde_json::from_str::<LuaValue>(&json).unwrap(), value);
+        
+        let float: LuaValue = serde_json::from_str(r#"{"type": "float", "value": 1}"#).unwrap();
+        assert_eq!(float, LuaValue::Float(1.0));
+    }
+    
+    #[test]
+    fn latin1_files_refuse_unencodable_text() {
+        let mut document = LuaDocument::parse_bytes(b"Name = \"Caf\xe9\"\n").unwrap();
+        assert_eq!(document.to_bytes().unwrap(), b"Name = \"Caf\xe9\"\n");
+        
+        document.set(&[key("Name")], LuaValue::String("\u{20ac}".to_string())).unwrap();
+        assert!(document.to_bytes().is_err());
+    }
+    
+    #[test]
+    fn expressions_are_rejected() {
+        assert!(LuaDocument::parse("A = B").is_err());
+        assert!(LuaDocument::parse("A = 1 + 2").is_err());
+        assert!(LuaDocument::parse("A = { f() }").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use crate::config::MirrorConfig;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const BASE_COOLDOWN: Duration = Duration::from_secs(30);
+const MAX_COOLDOWN: Duration = Duration::from_secs(600);
+// Mirrors whose expected response times are this close count as equally
+// fast, so the configured priority still decides between them.
+const SCORE_BUCKET_MS: u64 = 50;
+// Transfer size used to weigh measured throughput against latency.
+const REFERENCE_BYTES: f64 = 1024.0 * 1024.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorStatus {
+    pub name: String,
+    pub url: String,
+    pub priority: u32,
+    pub healthy: bool,
+    pub latency_ms: Option<u64>,
+    // Bytes per second, averaged over this session's downloads.
+    pub throughput_bps: Option<u64>,
+    pub consecutive_failures: u32,
+    pub cooldown_secs: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct MirrorRecord {
+    latency: Option<Duration>,
+    throughput: Option<f64>,
+    failures: u32,
+    cooldown_until: Option<Instant>,
+    last_error: Option<String>,
+}
+
+impl MirrorRecord {
+    fn cooldown_remaining(&self, now: Instant) -> Duration {
+        self.cooldown_until
+            .map(|until| until.saturating_duration_since(now))
+            .unwrap_or_default()
+    }
+    
+    // Rough time to fetch REFERENCE_BYTES; None until the mirror answered a
+    // probe. Mirrors nothing was downloaded from yet are assumed to be as fast
+    // as `default_bps`, so they aren't ranked ahead of measured ones for free.
+    fn score_ms(&self, default_bps: Option<f64>) -> Option<u64> {
+        let latency = self.latency?.as_secs_f64();
+        let transfer = self.throughput
+            .or(default_bps)
+            .filter(|bps| *bps > 0.0)
+            .map(|bps| REFERENCE_BYTES / bps)
+            .unwrap_or(0.0);
+        Some(((latency + transfer) * 1000.0) as u64)
+    }
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+    mirrors: HashMap<String, MirrorRecord>,
+    probed: bool,
+}
+
+// Per-session view of how the configured mirrors are doing. Cloning shares
+// the same state, so the UI can keep one instance across patch runs.
+#[derive(Debug, Clone, Default)]
+pub struct MirrorHealth {
+    state: Arc<Mutex<HealthState>>,
+}
+
+impl MirrorHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    // Probes every mirror once per session; later calls return immediately.
+    pub async fn ensure_probed(&self, client: &Client, mirrors: &[MirrorConfig]) {
+        if self.state.lock().unwrap().probed {
+            return;
+        }
+        self.probe_all(client, mirrors).await;
+    }
+    
+    // Sends a HEAD request to every mirror at once with a short timeout. Any
+    // answer below 500 counts as reachable, since mirror roots often 403/404.
+    pub async fn probe_all(&self, client: &Client, mirrors: &[MirrorConfig]) {
+        let probes = mirrors
+            .iter()
+            .filter(|mirror| !mirror.url.is_empty())
+            .map(|mirror| async move {
+                let started = Instant::now();
+                let result = client
+                    .head(&mirror.url)
+                    .timeout(PROBE_TIMEOUT)
+                    .send()
+                    .await;
+                (mirror, started.elapsed(), result)
+            });
+        
+        for (mirror, elapsed, result) in futures::future::join_all(probes).await {
+            match result {
+                Ok(response) if !response.status().is_server_error() => {
+                    debug!("Mirror {} answered in {} ms", mirror.name, elapsed.as_millis());
+                    self.record_latency(&mirror.url, elapsed);
+                }
+                Ok(response) => {
+                    self.record_failure(&mirror.url, &format!("HTTP {}", response.status()));
+                }
+                Err(e) => self.record_failure(&mirror.url, &e.to_string()),
+            }
+        }
+        
+        self.state.lock().unwrap().probed = true;
+        info!("Probed {} mirror(s)", mirrors.len());
+    }
+    
+    fn record_latency(&self, url: &str, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let record = state.mirrors.entry(url.to_string()).or_default();
+        record.latency = Some(latency);
+        record.failures = 0;
+        record.cooldown_until = None;
+        record.last_error = None;
+    }
+    
+    // Clears any cooldown and folds the transfer rate into the running average.
+    pub fn record_success(&self, url: &str, bytes: u64, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let record = state.mirrors.entry(url.to_string()).or_default();
+        record.failures = 0;
+        record.cooldown_until = None;
+        record.last_error = None;
+        
+        if bytes > 0 && !elapsed.is_zero() {
+            let rate = bytes as f64 / elapsed.as_secs_f64();
+            record.throughput = Some(match record.throughput {
+                Some(previous) => previous * 0.7 + rate * 0.3,
+                None => rate,
+            });
+        }
+    }
+    
+    // Puts the mirror on a cooldown that doubles with each consecutive failure.
+    pub fn record_failure(&self, url: &str, error: &str) {
+        let mut state = self.state.lock().unwrap();
+        let record = state.mirrors.entry(url.to_string()).or_default();
+        record.failures += 1;
+        
+        let cooldown = BASE_COOLDOWN
+            .saturating_mul(1 << (record.failures - 1).min(10))
+            .min(MAX_COOLDOWN);
+        record.cooldown_until = Some(Instant::now() + cooldown);
+        record.last_error = Some(error.to_string());
+        
+        warn!("Mirror {} failed ({}), cooling down for {}s", url, error, cooldown.as_secs());
+    }
+    
+    pub fn is_available(&self, url: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state.mirrors
+            .get(url)
+            .map(|record| record.cooldown_remaining(Instant::now()).is_zero())
+            .unwrap_or(true)
+    }
+    
+    // Orders mirrors for this session: available ones by measured speed, then
+    // priority; mirrors on cooldown last, soonest to recover first, so they
+    // are only tried once everything else failed.
+    pub fn order(&self, mut mirrors: Vec<MirrorConfig>) -> Vec<MirrorConfig> {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let fastest = state.mirrors
+            .values()
+            .filter_map(|record| record.throughput)
+            .reduce(f64::max);
+        
+        mirrors.sort_by_cached_key(|mirror| {
+            let record = state.mirrors.get(&mirror.url);
+            let cooldown = record
+                .map(|r| r.cooldown_remaining(now))
+                .unwrap_or_default();
+            let score = record
+                .and_then(|r| r.score_ms(fastest))
+                .map(|ms| ms / SCORE_BUCKET_MS)
+                .unwrap_or(u64::MAX);
+            (!cooldown.is_zero(), cooldown, score, mirror.priority)
+        });
+        
+        mirrors
+    }
+    
+    // Status of every configured mirror, in the order downloads will use them.
+    pub fn snapshot(&self, mirrors: &[MirrorConfig]) -> Vec<MirrorStatus> {
+        let ordered = self.order(mirrors.to_vec());
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        
+        ordered
+            .into_iter()
+            .map(|mirror| {
+                let record = state.mirrors.get(&mirror.url);
+                let cooldown = record
+                    .map(|r| r.cooldown_remaining(now))
+                    .unwrap_or_default();
+                
+                MirrorStatus {
+                    healthy: cooldown.is_zero(),
+                    latency_ms: record.and_then(|r| r.latency).map(|l| l.as_millis() as u64),
+                    throughput_bps: record.and_then(|r| r.throughput).map(|t| t as u64),
+                    consecutive_failures: record.map(|r| r.failures).unwrap_or(0),
+                    cooldown_secs: cooldown.as_secs(),
+                    last_error: record.and_then(|r| r.last_error.clone()),
+                    name: mirror.name,
+                    url: mirror.url,
+                    priority: mirror.priority,
+                }
+            })
+            .collect()
+    }
+}
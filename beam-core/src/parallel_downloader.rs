@@ -1,21 +1,40 @@
 use crate::{Config, Error, Result};
+use crate::control::PatchControl;
+use crate::downloader::content_range_start;
+use crate::partial_download::{PartialDownload, Validators};
+use crate::rate_limit::RateLimiter;
 use futures::stream::{FuturesUnordered, StreamExt};
 use reqwest::Client;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs::{File, OpenOptions};
 use tokio::io::{AsyncSeekExt, AsyncWriteExt};
-use tokio::sync::Semaphore;
+use tokio::sync::{mpsc, Semaphore};
 use tracing::{debug, info, warn};
 
-const CHUNK_SIZE: u64 = 1024 * 1024 * 2;
+pub const CHUNK_SIZE: u64 = 1024 * 1024 * 2;
 const MAX_PARALLEL_CHUNKS: usize = 4;
 
 pub struct ParallelDownloader {
     client: Client,
-    #[allow(dead_code)]
     config: Config,
     max_parallel: usize,
+    limiter: RateLimiter,
+    control: PatchControl,
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoteFileInfo {
+    pub size: Option<u64>,
+    pub accepts_ranges: bool,
+    pub validators: Validators,
+}
+
+impl RemoteFileInfo {
+    // Whether the file is worth fetching as parallel range requests.
+    pub fn supports_chunked(&self) -> bool {
+        self.accepts_ranges && self.size.is_some_and(|size| size >= CHUNK_SIZE)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -32,20 +51,24 @@ impl ParallelDownloader {
             .timeout(std::time::Duration::from_secs(300))
             .build()?;
         
-        Ok(ParallelDownloader {
+        let limiter = RateLimiter::new(config.patcher.bandwidth.clone());
+        Ok(Self::with_client(client, config, limiter, PatchControl::new()))
+    }
+    
+    // Shares the caller's HTTP client (and its connection pool and timeouts),
+    // bandwidth limit and pause/cancel control.
+    pub fn with_client(client: Client, config: Config, limiter: RateLimiter, control: PatchControl) -> Self {
+        ParallelDownloader {
             client,
             config,
             max_parallel: MAX_PARALLEL_CHUNKS,
-        })
+            limiter,
+            control,
+        }
     }
     
-    pub async fn download_file_parallel(
-        &self,
-        url: &str,
-        destination: &Path,
-    ) -> Result<PathBuf> {
-        info!("Starting parallel download: {}", url);
-        
+    // HEAD request telling whether the server advertises byte ranges.
+    pub async fn probe(&self, url: &str) -> Result<RemoteFileInfo> {
         let head_response = self.client.head(url).send().await?;
         
         if !head_response.status().is_success() {
@@ -55,67 +78,195 @@ impl ParallelDownloader {
             )));
         }
         
-        let total_size = head_response
-            .content_length()
-            .ok_or_else(|| Error::DownloadFailed("Content-Length header missing".to_string()))?;
-        
-        let supports_range = head_response
+        let accepts_ranges = head_response
             .headers()
             .get("accept-ranges")
             .map(|v| v.to_str().unwrap_or("") == "bytes")
             .unwrap_or(false);
         
-        if !supports_range || total_size < CHUNK_SIZE {
+        // content_length() reflects the (empty) HEAD body, so read the header.
+        let size = head_response
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        
+        Ok(RemoteFileInfo {
+            size,
+            accepts_ranges,
+            validators: Validators::from_headers(head_response.headers()),
+        })
+    }
+    
+    pub async fn download_file_parallel(
+        &self,
+        url: &str,
+        destination: &Path,
+    ) -> Result<PathBuf> {
+        info!("Starting parallel download: {}", url);
+        
+        let remote = self.probe(url).await?;
+        
+        let total_size = remote
+            .size
+            .ok_or_else(|| Error::DownloadFailed("Content-Length header missing".to_string()))?;
+        
+        if !remote.supports_chunked() {
             info!("Server doesn't support range requests or file too small, using single-threaded download");
             return self.download_single_threaded(url, destination, total_size).await;
         }
         
-        info!("Downloading {} bytes in parallel chunks", total_size);
+        self.download_chunks(&[(url.to_string(), remote)], destination, total_size, &mut |_, _| {}).await
+    }
+    
+    // Fetches `total_size` bytes as concurrent range requests into
+    // `<destination>.part`, moved into place once complete. Chunks are striped
+    // across `sources` (mirrors serving the same file, all accepting ranges;
+    // see probe()), and a failed chunk is retried on the next mirror before
+    // the download gives up. Finished chunks are recorded in a sidecar file,
+    // so an interrupted download continues where it stopped.
+    pub async fn download_chunks<F>(
+        &self,
+        sources: &[(String, RemoteFileInfo)],
+        destination: &Path,
+        total_size: u64,
+        progress_callback: &mut F,
+    ) -> Result<PathBuf>
+    where
+        F: FnMut(u64, u64),
+    {
+        if sources.is_empty() {
+            return Err(Error::DownloadFailed("No mirrors to download from".to_string()));
+        }
+        
+        info!("Downloading {} bytes in parallel chunks from {} mirror(s)", total_size, sources.len());
+        
+        let urls: Vec<String> = sources.iter().map(|(url, _)| url.clone()).collect();
+        let validators: Vec<(String, Validators)> = sources
+            .iter()
+            .map(|(url, remote)| (url.clone(), remote.validators.clone()))
+            .collect();
         
         tokio::fs::create_dir_all(destination.parent().unwrap()).await?;
+        let mut partial = PartialDownload::resume_or_new(destination, Some(total_size), &validators)?;
+        let part_path = PartialDownload::part_path(destination);
+        
         let file = OpenOptions::new()
             .write(true)
             .create(true)
-            .open(destination)
+            .truncate(false)
+            .open(&part_path)
             .await?;
         file.set_len(total_size).await?;
         drop(file);
+        partial.save(destination)?;
         
         let semaphore = Arc::new(Semaphore::new(self.max_parallel));
         let mut futures = FuturesUnordered::new();
+        let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
         
-        let num_chunks = (total_size + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        let num_chunks = total_size.div_ceil(CHUNK_SIZE);
         
         for chunk_index in 0..num_chunks {
             let start = chunk_index * CHUNK_SIZE;
             let end = std::cmp::min(start + CHUNK_SIZE - 1, total_size - 1);
             
-            let client = self.client.clone();
-            let url = url.to_string();
-            let destination = destination.to_path_buf();
-            let permit = semaphore.clone().acquire_owned().await.unwrap();
+            if partial.covers(start, end + 1) {
+                continue;
+            }
+            
+            let context = ChunkContext {
+                client: self.client.clone(),
+                limiter: self.limiter.clone(),
+                control: self.control.clone(),
+                part_path: part_path.clone(),
+                progress_tx: progress_tx.clone(),
+            };
+            let retry = self.config.patcher.retry.clone();
+            let urls = urls.clone();
+            let semaphore = semaphore.clone();
             
             futures.push(tokio::spawn(async move {
-                let result = download_chunk(&client, &url, &destination, start, end, chunk_index).await;
-                drop(permit);
-                result
+                let _permit = semaphore.acquire_owned().await.unwrap();
+                
+                // Transient errors are retried on the same mirror first, then
+                // the chunk moves on to the next one.
+                let mut last_error = None;
+                for attempt in 0..urls.len() {
+                    let url = &urls[(chunk_index as usize + attempt) % urls.len()];
+                    let what = format!("Chunk {} from {}", chunk_index, url);
+                    let result = crate::retry::with_retry(&retry, &what, || {
+                        download_chunk(&context, url, start, end, chunk_index)
+                    })
+                    .await;
+                    
+                    match result {
+                        Ok(()) => return Ok((start, end)),
+                        Err(Error::Cancelled) => return Err(Error::Cancelled),
+                        Err(e) => {
+                            warn!("Chunk {} failed from {}: {}", chunk_index, url, e);
+                            last_error = Some(e);
+                        }
+                    }
+                }
+                Err(last_error.unwrap())
             }));
         }
+        drop(progress_tx);
+        
+        let mut downloaded = partial.completed_bytes();
+        if downloaded > 0 {
+            progress_callback(downloaded, total_size);
+        }
         
-        while let Some(result) = futures.next().await {
-            match result {
-                Ok(Ok(_)) => {}
-                Ok(Err(e)) => {
-                    warn!("Chunk download failed: {}", e);
-                    return Err(e);
+        loop {
+            let error = tokio::select! {
+                Some(delta) = progress_rx.recv() => {
+                    downloaded = downloaded.saturating_add_signed(delta);
+                    progress_callback(downloaded, total_size);
+                    continue;
                 }
-                Err(e) => {
-                    warn!("Task join error: {}", e);
-                    return Err(Error::DownloadFailed(format!("Task join error: {}", e)));
+                result = futures.next() => match result {
+                    Some(Ok(Ok((start, end)))) => {
+                        partial.mark_completed(start, end + 1);
+                        match partial.save(destination) {
+                            Ok(()) => continue,
+                            Err(e) => e,
+                        }
+                    }
+                    Some(Ok(Err(e))) => e,
+                    Some(Err(e)) => Error::DownloadFailed(format!("Task join error: {}", e)),
+                    None => break,
+                },
+            };
+            
+            // Stop the remaining chunks and wait for them, so nothing writes
+            // to the .part file after we return; what finished so far is kept
+            // for resuming.
+            warn!("Chunk download failed: {}", error);
+            for handle in futures.iter() {
+                handle.abort();
+            }
+            while let Some(result) = futures.next().await {
+                if let Ok(Ok((start, end))) = result {
+                    partial.mark_completed(start, end + 1);
                 }
             }
+            if let Err(e) = partial.save(destination) {
+                warn!("Could not record finished chunks: {}", e);
+            }
+            return Err(error);
+        }
+        
+        // Every chunk task has finished, so the channel only holds what they
+        // sent before ending.
+        while let Ok(delta) = progress_rx.try_recv() {
+            downloaded = downloaded.saturating_add_signed(delta);
+            progress_callback(downloaded, total_size);
         }
         
+        PartialDownload::finish(destination)?;
+        
         info!("Parallel download completed: {:?}", destination);
         Ok(destination.to_path_buf())
     }
@@ -147,6 +298,8 @@ impl ParallelDownloader {
             let chunk = chunk?;
             file.write_all(&chunk).await?;
             downloaded += chunk.len() as u64;
+            self.limiter.acquire(chunk.len()).await;
+            self.control.checkpoint().await?;
             
             if downloaded % (1024 * 1024 * 10) == 0 || downloaded == total_size {
                 let percentage = (downloaded as f32 / total_size as f32) * 100.0;
@@ -171,9 +324,10 @@ impl ParallelDownloader {
         
         let current_size = tokio::fs::metadata(destination).await?.len();
         
-        let head_response = self.client.head(url).send().await?;
-        let total_size = head_response
-            .content_length()
+        let total_size = self
+            .probe(url)
+            .await?
+            .size
             .ok_or_else(|| Error::DownloadFailed("Content-Length header missing".to_string()))?;
         
         if current_size >= total_size {
@@ -213,6 +367,8 @@ impl ParallelDownloader {
             let chunk = chunk?;
             file.write_all(&chunk).await?;
             downloaded += chunk.len() as u64;
+            self.limiter.acquire(chunk.len()).await;
+            self.control.checkpoint().await?;
             
             if downloaded % (1024 * 1024 * 10) == 0 || downloaded == total_size {
                 let percentage = (downloaded as f32 / total_size as f32) * 100.0;
@@ -227,44 +383,126 @@ impl ParallelDownloader {
     }
 }
 
+// What every chunk task of one download shares.
+struct ChunkContext {
+    client: Client,
+    limiter: RateLimiter,
+    control: PatchControl,
+    part_path: PathBuf,
+    progress_tx: mpsc::UnboundedSender<i64>,
+}
+
 async fn download_chunk(
-    client: &Client,
+    context: &ChunkContext,
     url: &str,
-    destination: &Path,
     start: u64,
     end: u64,
     chunk_index: u64,
 ) -> Result<()> {
     debug!(
-        "Downloading chunk {}: bytes {}-{}",
-        chunk_index, start, end
+        "Downloading chunk {}: bytes {}-{} from {}",
+        chunk_index, start, end, url
     );
     
-    let response = client
+    let mut received = 0u64;
+    let result = fetch_range(context, url, start, end, &mut received).await;
+    
+    // Take back the progress of a failed attempt; the chunk will be fetched again.
+    if result.is_err() && received > 0 {
+        let _ = context.progress_tx.send(-(received as i64));
+    }
+    
+    if result.is_ok() {
+        debug!("Chunk {} downloaded successfully", chunk_index);
+    }
+    
+    result
+}
+
+async fn fetch_range(
+    context: &ChunkContext,
+    url: &str,
+    start: u64,
+    end: u64,
+    received: &mut u64,
+) -> Result<()> {
+    let response = context
+        .client
         .get(url)
         .header("Range", format!("bytes={}-{}", start, end))
         .send()
         .await?;
     
-    if !response.status().is_success() && response.status().as_u16() != 206 {
+    // Anything but 206 would write the wrong bytes at this offset; a server
+    // that ignores Range answers 200 with the whole file.
+    if !response.status().is_success() {
+        return Err(Error::Http {
+            url: url.to_string(),
+            status: response.status().as_u16(),
+        });
+    }
+    
+    if response.status().as_u16() != 206 {
         return Err(Error::DownloadFailed(format!(
-            "HTTP error: {}",
+            "HTTP error for range request: {}",
             response.status()
         )));
     }
     
-    let bytes = response.bytes().await?;
+    // Bytes from another offset would land on the wrong part of the file.
+    if content_range_start(response.headers()) != Some(start) {
+        return Err(Error::DownloadFailed(format!(
+            "{} answered range {}-{} with {:?}",
+            url,
+            start,
+            end,
+            response.headers().get(reqwest::header::CONTENT_RANGE)
+        )));
+    }
     
     let mut file = OpenOptions::new()
         .write(true)
-        .open(destination)
+        .open(&context.part_path)
         .await?;
     
     file.seek(std::io::SeekFrom::Start(start)).await?;
-    file.write_all(&bytes).await?;
+    
+    let expected = end - start + 1;
+    let mut stream = response.bytes_stream();
+    
+    while let Some(bytes) = stream.next().await {
+        let bytes = bytes.map_err(|e| Error::Interrupted(format!(
+            "range {}-{} after {} bytes: {}",
+            start, end, received, e
+        )))?;
+        
+        // Anything past `end` belongs to the neighbouring chunk.
+        let wanted = (expected - *received).min(bytes.len() as u64) as usize;
+        if wanted < bytes.len() {
+            warn!("{} sent more than range {}-{}, ignoring the rest", url, start, end);
+        }
+        let bytes = bytes.slice(..wanted);
+        
+        file.write_all(&bytes).await?;
+        *received += bytes.len() as u64;
+        let _ = context.progress_tx.send(bytes.len() as i64);
+        context.limiter.acquire(bytes.len()).await;
+        context.control.checkpoint().await?;
+        
+        if *received == expected {
+            break;
+        }
+    }
+    
     file.flush().await?;
+    file.sync_data().await?;
     
-    debug!("Chunk {} downloaded successfully", chunk_index);
+    if *received != expected {
+        return Err(Error::Interrupted(format!(
+            "Range {}-{} returned {} bytes, expected {}",
+            start, end, received, expected
+        )));
+    }
     
     Ok(())
 }
@@ -0,0 +1,310 @@
+use crate::Result;
+use reqwest::header::{HeaderMap, HeaderName, ETAG, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+// Cache validators a server sent for a URL, used to tell whether a partially
+// downloaded file still matches what the server has now.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name: HeaderName| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        
+        Validators {
+            etag: get(ETAG),
+            last_modified: get(LAST_MODIFIED),
+        }
+    }
+    
+    // Value for an If-Range header; strong ETags are preferred.
+    pub fn if_range(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+}
+
+// Sidecar for `<file>.part`, stored as `<file>.part.json`. Records which byte
+// ranges of the download are already on disk so a later run can pick up
+// where the previous one stopped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialDownload {
+    pub size: Option<u64>,
+    pub sources: BTreeMap<String, Validators>,
+    // Sorted, non-overlapping half-open byte ranges.
+    pub completed: Vec<(u64, u64)>,
+}
+
+impl PartialDownload {
+    pub fn new(size: Option<u64>) -> Self {
+        PartialDownload {
+            size,
+            sources: BTreeMap::new(),
+            completed: Vec::new(),
+        }
+    }
+    
+    pub fn part_path(destination: &Path) -> PathBuf {
+        with_suffix(destination, ".part")
+    }
+    
+    fn sidecar_path(destination: &Path) -> PathBuf {
+        with_suffix(destination, ".part.json")
+    }
+    
+    // Loads the sidecar for `destination` if both it and the .part file exist.
+    pub fn load(destination: &Path) -> Option<Self> {
+        if !Self::part_path(destination).exists() {
+            return None;
+        }
+        
+        let content = std::fs::read_to_string(Self::sidecar_path(destination)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+    
+    // Resumes the partial download for `destination` if it was fetched from
+    // any of `sources` and none of them changed since; otherwise starts over.
+    pub fn resume_or_new(destination: &Path, size: Option<u64>, sources: &[(String, Validators)]) -> Result<Self> {
+        if let Some(mut partial) = Self::load(destination) {
+            if partial.size == size && partial.matches_sources(sources) {
+                info!("Resuming {:?}: {} bytes already downloaded", destination, partial.completed_bytes());
+                partial.sources.extend(sources.iter().cloned());
+                return Ok(partial);
+            }
+            
+            debug!("Discarding stale partial download {:?}", destination);
+        }
+        
+        Self::discard(destination)?;
+        
+        let mut partial = Self::new(size);
+        partial.sources.extend(sources.iter().cloned());
+        Ok(partial)
+    }
+    
+    // True when at least one URL overlaps with the recorded sources and every
+    // overlapping URL still reports the same validators.
+    fn matches_sources(&self, sources: &[(String, Validators)]) -> bool {
+        let mut overlap = false;
+        
+        for (url, validators) in sources {
+            if let Some(recorded) = self.sources.get(url) {
+                if recorded != validators {
+                    return false;
+                }
+                overlap = true;
+            }
+        }
+        
+        overlap
+    }
+    
+    pub fn save(&self, destination: &Path) -> Result<()> {
+        crate::write_file_atomic(
+            &Self::sidecar_path(destination),
+            serde_json::to_string(self)?.as_bytes(),
+        )
+    }
+    
+    pub fn discard(destination: &Path) -> Result<()> {
+        for path in [Self::part_path(destination), Self::sidecar_path(destination)] {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+    
+    // Moves the completed .part file into place and drops the sidecar.
+    pub fn finish(destination: &Path) -> Result<()> {
+        std::fs::rename(Self::part_path(destination), destination)?;
+        
+        let sidecar = Self::sidecar_path(destination);
+        if sidecar.exists() {
+            std::fs::remove_file(sidecar)?;
+        }
+        Ok(())
+    }
+    
+    pub fn mark_completed(&mut self, start: u64, end: u64) {
+        if start >= end {
+            return;
+        }
+        
+        self.completed.push((start, end));
+        self.completed.sort_unstable();
+        
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(self.completed.len());
+        for &(s, e) in &self.completed {
+            match merged.last_mut() {
+                Some(last) if s <= last.1 => last.1 = last.1.max(e),
+                _ => merged.push((s, e)),
+            }
+        }
+        self.completed = merged;
+    }
+    
+    pub fn covers(&self, start: u64, end: u64) -> bool {
+        self.completed.iter().any(|&(s, e)| s <= start && end <= e)
+    }
+    
+    pub fn completed_bytes(&self) -> u64 {
+        self.completed.iter().map(|(s, e)| e - s).sum()
+    }
+    
+    // Whether every byte of a download of known size is already on disk, as
+    // when a run stopped between the last chunk and moving the file into place.
+    pub fn is_complete(&self) -> bool {
+        self.size.is_some_and(|size| self.contiguous_prefix() >= size)
+    }
+    
+    // Length of the completed range starting at byte 0, the part a plain
+    // `Range: bytes=N-` request can continue from.
+    pub fn contiguous_prefix(&self) -> u64 {
+        match self.completed.first() {
+            Some(&(0, end)) => end,
+            _ => 0,
+        }
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const URL: &str = "https://patch.example.com/data/update.thor";
+    
+    fn validators(etag: &str) -> Validators {
+        Validators {
+            etag: Some(etag.to_string()),
+            last_modified: None,
+        }
+    }
+    
+    // Leaves a .part file with `completed` recorded, as an interrupted run would.
+    fn interrupted(destination: &Path, size: Option<u64>, etag: &str, completed: (u64, u64)) {
+        std::fs::write(PartialDownload::part_path(destination), vec![0u8; completed.1 as usize]).unwrap();
+        
+        let mut partial = PartialDownload::new(size);
+        partial.sources.insert(URL.to_string(), validators(etag));
+        partial.mark_completed(completed.0, completed.1);
+        partial.save(destination).unwrap();
+    }
+    
+    #[test]
+    fn resumes_when_source_is_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("update.thor");
+        interrupted(&destination, Some(100), "\"v1\"", (0, 40));
+        
+        let partial = PartialDownload::resume_or_new(&destination, Some(100), &[(URL.to_string(), validators("\"v1\""))]).unwrap();
+        assert_eq!(partial.contiguous_prefix(), 40);
+        assert!(PartialDownload::part_path(&destination).exists());
+    }
+    
+    #[test]
+    fn starts_over_when_the_server_copy_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("update.thor");
+        let current = [(URL.to_string(), validators("\"v1\""))];
+        
+        // Different ETag.
+        interrupted(&destination, Some(100), "\"v0\"", (0, 40));
+        let partial = PartialDownload::resume_or_new(&destination, Some(100), &current).unwrap();
+        assert_eq!(partial.completed_bytes(), 0);
+        assert!(!PartialDownload::part_path(&destination).exists());
+        
+        // Different size in the patch list.
+        interrupted(&destination, Some(90), "\"v1\"", (0, 40));
+        let partial = PartialDownload::resume_or_new(&destination, Some(100), &current).unwrap();
+        assert_eq!(partial.completed_bytes(), 0);
+        
+        // Fetched from a mirror that isn't used any more.
+        interrupted(&destination, Some(100), "\"v1\"", (0, 40));
+        let other = [("https://other.example.com/update.thor".to_string(), validators("\"v1\""))];
+        let partial = PartialDownload::resume_or_new(&destination, Some(100), &other).unwrap();
+        assert_eq!(partial.completed_bytes(), 0);
+        assert!(partial.sources.contains_key(&other[0].0));
+    }
+    
+    #[test]
+    fn sidecar_without_part_file_or_unreadable_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("update.thor");
+        
+        interrupted(&destination, Some(100), "\"v1\"", (0, 40));
+        std::fs::remove_file(PartialDownload::part_path(&destination)).unwrap();
+        assert!(PartialDownload::load(&destination).is_none());
+        
+        interrupted(&destination, Some(100), "\"v1\"", (0, 40));
+        std::fs::write(dir.path().join("update.thor.part.json"), b"{\"size\":").unwrap();
+        assert!(PartialDownload::load(&destination).is_none());
+    }
+    
+    #[test]
+    fn finish_moves_part_into_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("update.thor");
+        interrupted(&destination, Some(4), "\"v1\"", (0, 4));
+        
+        PartialDownload::finish(&destination).unwrap();
+        assert_eq!(std::fs::read(&destination).unwrap().len(), 4);
+        assert!(!PartialDownload::part_path(&destination).exists());
+        assert!(!dir.path().join("update.thor.part.json").exists());
+    }
+    
+    #[test]
+    fn completed_ranges_merge() {
+        let mut partial = PartialDownload::new(Some(100));
+        partial.mark_completed(50, 60);
+        partial.mark_completed(10, 20);
+        partial.mark_completed(20, 30);
+        partial.mark_completed(55, 70);
+        partial.mark_completed(5, 5);
+        
+        assert_eq!(partial.completed, vec![(10, 30), (50, 70)]);
+        assert_eq!(partial.completed_bytes(), 40);
+        assert_eq!(partial.contiguous_prefix(), 0);
+        assert!(partial.covers(12, 30));
+        assert!(!partial.covers(25, 55));
+        
+        partial.mark_completed(0, 10);
+        assert_eq!(partial.contiguous_prefix(), 30);
+    }
+    
+    #[test]
+    fn if_range_prefers_strong_etag() {
+        let both = Validators {
+            etag: Some("\"v1\"".to_string()),
+            last_modified: Some("Mon, 01 Jan 2024 00:00:00 GMT".to_string()),
+        };
+        assert_eq!(both.if_range(), Some("\"v1\""));
+        
+        let weak = Validators {
+            etag: Some("W/\"v1\"".to_string()),
+            ..both.clone()
+        };
+        assert_eq!(weak.if_range(), both.last_modified.as_deref());
+        
+        assert_eq!(Validators::default().if_range(), None);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::change_log::PatchChanges;
+use crate::downloader::PatchInfo;
+use crate::Result;
+use beam_formats::grf::Grf;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const JOURNAL_FILE: &str = "patch_journal.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalStage {
+    // Patch is downloaded and verified, the GRF hasn't been touched yet.
+    Downloaded,
+    // The rebuilt GRF, or the patch's loose files, are being written to staging files.
+    Applying,
+    // The staging files are complete and synced, but not renamed into place.
+    Staged,
+    // The staging files replaced the originals; only bookkeeping is left.
+    Committed,
+}
+
+// A loose file a patch writes or removes. New contents are staged next to
+// the target and only renamed over it once every file of the patch is staged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LooseFile {
+    pub path: PathBuf,
+    // None when the patch removes the file.
+    pub staged: Option<PathBuf>,
+}
+
+impl LooseFile {
+    pub fn write(path: PathBuf) -> Self {
+        let mut staged = path.as_os_str().to_owned();
+        staged.push(".new");
+        LooseFile {
+            staged: Some(PathBuf::from(staged)),
+            path,
+        }
+    }
+    
+    pub fn remove(path: PathBuf) -> Self {
+        LooseFile { path, staged: None }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    // Patch list entry to mark as applied once committed; None for manual patches.
+    pub patch: Option<PatchInfo>,
+    // Earlier patches merged into the same GRF save, marked applied with `patch`.
+    #[serde(default)]
+    pub merged: Vec<PatchInfo>,
+    // Change log records to write once committed, so recovery can log them too.
+    #[serde(default)]
+    pub change_log: Vec<PatchChanges>,
+    pub patch_path: PathBuf,
+    pub grf_path: Option<PathBuf>,
+    pub staged_path: Option<PathBuf>,
+    // Loose files of a patch that doesn't target a GRF.
+    #[serde(default)]
+    pub loose_files: Vec<LooseFile>,
+    pub stage: JournalStage,
+    pub started_at: u64,
+}
+
+impl JournalEntry {
+    pub fn new(patch: Option<PatchInfo>, patch_path: &Path) -> Self {
+        JournalEntry {
+            patch,
+            merged: Vec::new(),
+            change_log: Vec::new(),
+            patch_path: patch_path.to_path_buf(),
+            grf_path: None,
+            staged_path: None,
+            loose_files: Vec::new(),
+            stage: JournalStage::Downloaded,
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+}
+
+// Write-ahead record of the patch (or batch) currently being applied. Every stage change
+// is synced to disk before the step it describes, so after a crash the
+// patcher can tell whether the GRF or loose files were replaced and finish or
+// undo the patch.
+pub struct PatchJournal {
+    path: PathBuf,
+}
+
+impl PatchJournal {
+    pub fn new<P: AsRef<Path>>(cache_dir: P) -> Self {
+        PatchJournal {
+            path: cache_dir.as_ref().join(JOURNAL_FILE),
+        }
+    }
+    
+    pub fn load(&self) -> Result<Option<JournalEntry>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        
+        let content = std::fs::read_to_string(&self.path)?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+    
+    pub fn begin(&self, entry: &JournalEntry) -> Result<()> {
+        self.write(entry)
+    }
+    
+    // Moves the active entry to a new stage. Does nothing when no patch is in
+    // progress, so GRF writes outside a journaled patch still go through.
+    pub fn advance(
+        &self,
+        stage: JournalStage,
+        grf_path: Option<&Path>,
+        staged_path: Option<&Path>,
+    ) -> Result<()> {
+        let Some(mut entry) = self.load()? else {
+            return Ok(());
+        };
+        
+        entry.stage = stage;
+        if let Some(path) = grf_path {
+            entry.grf_path = Some(path.to_path_buf());
+        }
+        if let Some(path) = staged_path {
+            entry.staged_path = Some(path.to_path_buf());
+        }
+        
+        self.write(&entry)
+    }
+    
+    // Records the loose files about to be staged and moves to Applying. Like
+    // `advance`, does nothing when no patch is in progress.
+    pub fn stage_loose(&self, files: &[LooseFile]) -> Result<()> {
+        let Some(mut entry) = self.load()? else {
+            return Ok(());
+        };
+        
+        entry.stage = JournalStage::Applying;
+        entry.loose_files = files.to_vec();
+        
+        self.write(&entry)
+    }
+    
+    // Writes the GRF's pending changes through its staging file, advancing the
+    // active entry before each step.
+    pub fn save_grf(&self, grf: &mut Grf) -> Result<()> {
+        if !grf.has_pending_changes() {
+            return Ok(());
+        }
+        
+        self.advance(
+            JournalStage::Applying,
+            Some(grf.path()),
+            Some(&grf.staging_path()),
+        )?;
+        
+        let Some(staged_path) = grf.stage()? else {
+            return Ok(());
+        };
+        
+        self.advance(JournalStage::Staged, None, None)?;
+        grf.commit(&staged_path)?;
+        self.advance(JournalStage::Committed, None, None)?;
+        
+        Ok(())
+    }
+    
+    pub fn clear(&self) -> Result<()> {
+        if self.path.exists() {
+            std::fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+    
+    fn write(&self, entry: &JournalEntry) -> Result<()> {
+        crate::write_file_atomic(&self.path, serde_json::to_string_pretty(entry)?.as_bytes())
+    }
+}
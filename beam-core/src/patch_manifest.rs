@@ -0,0 +1,231 @@
+use crate::checksum::HashAlgorithm;
+use crate::downloader::PatchInfo;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+pub const MANIFEST_VERSION: u32 = 1;
+
+// Versioned alternative to the plain text patch list. Published as JSON or
+// YAML at the same patch_list_url; the format is detected from the content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchManifest {
+    pub version: u32,
+    pub patches: Vec<PatchInfo>,
+}
+
+// Written as a plain string in manifests: `loose`, or the GRF file name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum PatchTarget {
+    // Files are written into the named GRF in the game directory.
+    Grf(String),
+    // Files are extracted into the game directory as loose files.
+    Loose,
+}
+
+impl From<String> for PatchTarget {
+    fn from(value: String) -> Self {
+        if value.eq_ignore_ascii_case("loose") {
+            PatchTarget::Loose
+        } else {
+            PatchTarget::Grf(value)
+        }
+    }
+}
+
+impl From<PatchTarget> for String {
+    fn from(target: PatchTarget) -> Self {
+        match target {
+            PatchTarget::Grf(name) => name,
+            PatchTarget::Loose => "loose".to_string(),
+        }
+    }
+}
+
+pub fn parse_patch_list(content: &str) -> Result<Vec<PatchInfo>> {
+    let trimmed = content.trim_start_matches('\u{feff}').trim_start();
+    
+    let manifest: PatchManifest = if trimmed.starts_with('{') {
+        serde_json::from_str(trimmed)?
+    } else if trimmed.lines().any(|line| line.trim_end() == "patches:") {
+        serde_yaml::from_str(trimmed)?
+    } else {
+        return parse_text_list(content);
+    };
+    
+    if manifest.version > MANIFEST_VERSION {
+        return Err(Error::InvalidConfig(format!(
+            "Patch manifest version {} is newer than supported version {}",
+            manifest.version, MANIFEST_VERSION
+        )));
+    }
+    
+    validate(&manifest.patches)?;
+    Ok(manifest.patches)
+}
+
+// `filename [checksum] [size]` per line, `#` starts a comment. The checksum
+// may name its algorithm as `md5:<hex>`; a bare one is SHA-256.
+fn parse_text_list(content: &str) -> Result<Vec<PatchInfo>> {
+    let mut patches = Vec::new();
+    
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.is_empty() {
+            continue;
+        }
+        
+        let (hash_algorithm, checksum) = match parts.get(1).map(|s| s.split_once(':')) {
+            Some(Some((algorithm, checksum))) => {
+                HashAlgorithm::parse(Some(algorithm))?;
+                (Some(algorithm.to_string()), Some(checksum.to_string()))
+            }
+            Some(None) => (None, Some(parts[1].to_string())),
+            None => (None, None),
+        };
+        
+        patches.push(PatchInfo {
+            filename: parts[0].to_string(),
+            checksum,
+            size: parts.get(2).and_then(|s| s.parse::<u64>().ok()),
+            hash_algorithm,
+            ..Default::default()
+        });
+    }
+    
+    Ok(patches)
+}
+
+// Patches are applied in list order, so every dependency has to be listed
+// before the patch that needs it.
+fn validate(patches: &[PatchInfo]) -> Result<()> {
+    let mut seen = HashSet::new();
+    
+    for patch in patches {
+        if patch.filename.is_empty() || patch.filename.contains(['/', '\\']) {
+            return Err(Error::InvalidConfig(format!(
+                "Invalid patch file name in manifest: {:?}",
+                patch.filename
+            )));
+        }
+        
+        if let Some(PatchTarget::Grf(name)) = &patch.target {
+            if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+                return Err(Error::InvalidConfig(format!(
+                    "Invalid target GRF for {}: {:?}",
+                    patch.filename, name
+                )));
+            }
+        }
+        
+        HashAlgorithm::parse(patch.hash_algorithm.as_deref())?;
+        
+        for dependency in &patch.dependencies {
+            if !seen.contains(dependency.as_str()) {
+                return Err(Error::InvalidConfig(format!(
+                    "Patch {} depends on {}, which is not listed before it",
+                    patch.key(), dependency
+                )));
+            }
+        }
+        
+        if !seen.insert(patch.key()) {
+            return Err(Error::InvalidConfig(format!(
+                "Duplicate patch in manifest: {}",
+                patch.key()
+            )));
+        }
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn filenames(patches: &[PatchInfo]) -> Vec<&str> {
+        patches.iter().map(|p| p.filename.as_str()).collect()
+    }
+    
+    #[test]
+    fn detects_json_manifest() {
+        let content = r#"
+            {"version": 1, "patches": [
+                {"filename": "base.thor", "checksum": "aa", "size": 10, "id": "base"},
+                {"filename": "ui.gpf", "checksum": "bb", "size": null, "target": "loose", "dependencies": ["base"]}
+            ]}"#;
+        
+        let patches = parse_patch_list(content).unwrap();
+        assert_eq!(filenames(&patches), ["base.thor", "ui.gpf"]);
+        assert_eq!(patches[1].target, Some(PatchTarget::Loose));
+        assert_eq!(patches[1].dependencies, ["base"]);
+    }
+    
+    #[test]
+    fn detects_yaml_manifest() {
+        let content = "\u{feff}version: 1\npatches:\n  - filename: sprites.thor\n    checksum: cc\n    size: 5\n    target: rdata.grf\n    optional: true\n";
+        
+        let patches = parse_patch_list(content).unwrap();
+        assert_eq!(filenames(&patches), ["sprites.thor"]);
+        assert_eq!(patches[0].target, Some(PatchTarget::Grf("rdata.grf".to_string())));
+        assert!(patches[0].optional);
+    }
+    
+    #[test]
+    fn falls_back_to_text_list() {
+        let content = "# comment\nfirst.thor abc 100\n\nsecond.gpf md5:def\nthird.rgz\n";
+        
+        let patches = parse_patch_list(content).unwrap();
+        assert_eq!(filenames(&patches), ["first.thor", "second.gpf", "third.rgz"]);
+        assert_eq!(patches[0].checksum.as_deref(), Some("abc"));
+        assert_eq!(patches[0].size, Some(100));
+        assert_eq!(patches[1].hash_algorithm.as_deref(), Some("md5"));
+        assert_eq!(patches[1].checksum.as_deref(), Some("def"));
+        assert_eq!(patches[2].checksum, None);
+        
+        assert!(parse_patch_list("bad.thor sha512:abc").is_err());
+    }
+    
+    #[test]
+    fn rejects_newer_manifest_version() {
+        let content = r#"{"version": 99, "patches": []}"#;
+        assert!(matches!(parse_patch_list(content), Err(Error::InvalidConfig(_))));
+    }
+    
+    #[test]
+    fn dependencies_must_be_listed_first() {
+        let ordered = r#"{"version": 1, "patches": [
+            {"filename": "a.thor", "checksum": null, "size": null},
+            {"filename": "b.thor", "checksum": null, "size": null, "dependencies": ["a.thor"]}
+        ]}"#;
+        assert!(parse_patch_list(ordered).is_ok());
+        
+        let reversed = r#"{"version": 1, "patches": [
+            {"filename": "b.thor", "checksum": null, "size": null, "dependencies": ["a.thor"]},
+            {"filename": "a.thor", "checksum": null, "size": null}
+        ]}"#;
+        assert!(matches!(parse_patch_list(reversed), Err(Error::InvalidConfig(_))));
+        
+        let duplicate = r#"{"version": 1, "patches": [
+            {"filename": "a.thor", "checksum": null, "size": null},
+            {"filename": "a.thor", "checksum": null, "size": null}
+        ]}"#;
+        assert!(matches!(parse_patch_list(duplicate), Err(Error::InvalidConfig(_))));
+    }
+    
+    #[test]
+    fn rejects_paths_in_names() {
+        let filename = r#"{"version": 1, "patches": [{"filename": "../a.thor", "checksum": null, "size": null}]}"#;
+        assert!(parse_patch_list(filename).is_err());
+        
+        let target = r#"{"version": 1, "patches": [{"filename": "a.thor", "checksum": null, "size": null, "target": "..\\data.grf"}]}"#;
+        assert!(parse_patch_list(target).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+use crate::downloader::PatchInfo;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+const STATE_FILE: &str = "applied_patches.json";
+const LEGACY_STATE_FILE: &str = "applied_patches.txt";
+const STATE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedPatch {
+    pub filename: String,
+    pub checksum: Option<String>,
+    pub size: Option<u64>,
+    // Unix timestamp; 0 for entries migrated from applied_patches.txt.
+    pub applied_at: u64,
+    pub target: Option<String>,
+    pub patcher_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchStateData {
+    pub version: u32,
+    pub patches: BTreeMap<String, AppliedPatch>,
+}
+
+impl Default for PatchStateData {
+    fn default() -> Self {
+        PatchStateData {
+            version: STATE_VERSION,
+            patches: BTreeMap::new(),
+        }
+    }
+}
+
+// Record of every patch applied to a game install, kept in .patch_cache.
+pub struct PatchState {
+    path: PathBuf,
+    legacy_path: PathBuf,
+}
+
+impl PatchState {
+    pub fn new<P: AsRef<Path>>(cache_dir: P) -> Self {
+        PatchState {
+            path: cache_dir.as_ref().join(STATE_FILE),
+            legacy_path: cache_dir.as_ref().join(LEGACY_STATE_FILE),
+        }
+    }
+    
+    pub fn load(&self) -> Result<PatchStateData> {
+        if self.path.exists() {
+            let content = std::fs::read_to_string(&self.path)?;
+            return Ok(serde_json::from_str(&content)?);
+        }
+        
+        if self.legacy_path.exists() {
+            return self.migrate_legacy();
+        }
+        
+        Ok(PatchStateData::default())
+    }
+    
+    pub fn get(&self, filename: &str) -> Result<Option<AppliedPatch>> {
+        Ok(self.load()?.patches.remove(filename))
+    }
+    
+    pub fn record(&self, patch: &PatchInfo, target: Option<&str>) -> Result<()> {
+        let mut state = self.load()?;
+        
+        state.patches.insert(patch.filename.clone(), AppliedPatch {
+            filename: patch.filename.clone(),
+            checksum: patch.checksum.clone(),
+            size: patch.size,
+            applied_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            target: target.map(|t| t.to_string()),
+            patcher_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        });
+        
+        self.save(&state)
+    }
+    
+    // Filters a patch list down to the patches that still need applying: those
+    // never applied, and those re-published with a different checksum. Entries
+    // without a recorded checksum (migrated ones) adopt the list's checksum so
+    // later re-publishes are caught.
+    pub fn pending(&self, patches: Vec<PatchInfo>) -> Result<Vec<PatchInfo>> {
+        let mut state = self.load()?;
+        let mut backfilled = false;
+        let mut pending = Vec::new();
+        
+        for patch in patches {
+            let Some(applied) = state.patches.get_mut(&patch.filename) else {
+                pending.push(patch);
+                continue;
+            };
+            
+            match (&applied.checksum, &patch.checksum) {
+                (Some(old), Some(new)) if !old.eq_ignore_ascii_case(new) => {
+                    info!("Patch {} changed since it was applied ({} -> {}), reapplying",
+                        patch.filename, old, new);
+                    pending.push(patch);
+                }
+                (None, Some(new)) => {
+                    applied.checksum = Some(new.clone());
+                    backfilled = true;
+                }
+                _ => {}
+            }
+        }
+        
+        if backfilled {
+            self.save(&state)?;
+        }
+        
+        Ok(pending)
+    }
+    
+    pub fn clear(&self) -> Result<()> {
+        for path in [&self.path, &self.legacy_path] {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+    
+    fn save(&self, state: &PatchStateData) -> Result<()> {
+        crate::write_file_atomic(&self.path, serde_json::to_string_pretty(state)?.as_bytes())
+    }
+    
+    // Converts applied_patches.txt into the JSON store. The text file only
+    // knows names, so everything else stays empty until the next patch list.
+    fn migrate_legacy(&self) -> Result<PatchStateData> {
+        let content = std::fs::read_to_string(&self.legacy_path)?;
+        let mut state = PatchStateData::default();
+        
+        for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            state.patches.insert(line.to_string(), AppliedPatch {
+                filename: line.to_string(),
+                checksum: None,
+                size: None,
+                applied_at: 0,
+                target: None,
+                patcher_version: None,
+            });
+        }
+        
+        self.save(&state)?;
+        std::fs::remove_file(&self.legacy_path)?;
+        
+        info!("Migrated {} entries from {:?}", state.patches.len(), self.legacy_path);
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn patch(filename: &str, checksum: Option<&str>) -> PatchInfo {
+        PatchInfo {
+            filename: filename.to_string(),
+            checksum: checksum.map(str::to_string),
+            ..Default::default()
+        }
+    }
+    
+    fn filenames(patches: &[PatchInfo]) -> Vec<&str> {
+        patches.iter().map(|p| p.filename.as_str()).collect()
+    }
+    
+    #[test]
+    fn pending_skips_applied_and_catches_republished_patches() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = PatchState::new(dir.path());
+        state.record(&patch("a.thor", Some("AA")), None).unwrap();
+        state.record(&patch("b.thor", Some("bb")), None).unwrap();
+        state.record(&patch("c.thor", None), None).unwrap();
+        
+        let pending = state
+            .pending(vec![
+                patch("a.thor", Some("aa")),
+                patch("b.thor", Some("changed")),
+                patch("c.thor", None),
+                patch("d.thor", Some("dd")),
+            ])
+            .unwrap();
+        
+        assert_eq!(filenames(&pending), ["b.thor", "d.thor"]);
+    }
+    
+    #[test]
+    fn migrated_entries_adopt_the_list_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(LEGACY_STATE_FILE), "old.thor\nnochecksum.thor\n").unwrap();
+        let state = PatchState::new(dir.path());
+        let list = || vec![patch("old.thor", Some("11")), patch("nochecksum.thor", None)];
+        
+        // Upgrading must not reapply the whole patch history.
+        assert!(state.pending(list()).unwrap().is_empty());
+        assert!(!dir.path().join(LEGACY_STATE_FILE).exists());
+        assert_eq!(state.get("old.thor").unwrap().unwrap().checksum.as_deref(), Some("11"));
+        
+        // From then on a re-published patch is caught.
+        let changed = state.pending(vec![patch("old.thor", Some("22"))]).unwrap();
+        assert_eq!(filenames(&changed), ["old.thor"]);
+    }
+}
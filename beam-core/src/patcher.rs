@@ -1,28 +1,126 @@
 use crate::{Config, Downloader, Error, Result};
+use crate::config::BatchConfig;
+use crate::control::PatchControl;
+use crate::progress::ProgressTracker;
+use crate::plan::{PatchPlan, PlannedPatch, TargetChanges};
+use crate::change_log::{ChangeLog, EntryChange, PatchChanges};
+use crate::events::{EventSender, PatchEvent};
+use crate::client_info::ClientInfo;
+use crate::session::DownloadSession;
 use crate::downloader::PatchInfo;
+use crate::patch_journal::{JournalEntry, JournalStage, LooseFile, PatchJournal};
+use crate::patch_manifest::PatchTarget;
 use beam_formats::{grf::Grf, gpf::Gpf, rgz::Rgz, thor::Thor, beam::BeamArchive};
-use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
 use tracing::{debug, info, warn};
 
 pub struct Patcher {
     config: Config,
-    downloader: Downloader,
+    downloader: Arc<Downloader>,
+    journal: PatchJournal,
+    change_log: ChangeLog,
     temp_dir: PathBuf,
+    events: EventSender,
+    control: PatchControl,
+}
+
+// A downloaded patch waiting to be applied. The permit holds its share of the
+// prefetch disk budget until the patch is applied and deleted.
+struct Prefetched {
+    index: usize,
+    patch: PatchInfo,
+    path: PathBuf,
+    result: Result<PathBuf>,
+    _permit: OwnedSemaphorePermit,
+}
+
+// Consecutive patches for the same GRF, merged in memory and written by a
+// single rebuild in `commit_batch`.
+struct GrfBatch {
+    grf: Grf,
+    patches: Vec<(usize, PatchInfo, PathBuf)>,
+    change_log: Vec<PatchChanges>,
+}
+
+impl GrfBatch {
+    fn is_full(&self, limits: &BatchConfig) -> bool {
+        self.patches.len() >= limits.max_patches.max(1)
+            || self.grf.pending_bytes() >= limits.max_mb.saturating_mul(1024 * 1024)
+    }
+}
+
+// Stops the prefetching task when the run ends early.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecoveryOutcome {
+    // Nothing was replaced; the interrupted patch will be applied again.
+    RolledBack(String),
+    // The GRF or loose files were already being replaced; the patch was
+    // finished and recorded as applied.
+    RolledForward(String),
 }
 
 impl Patcher {
     pub fn new(config: Config) -> Result<Self> {
-        let downloader = Downloader::new(config.clone())?;
+        let session = DownloadSession::new(&config);
+        Self::with_session(config, session)
+    }
+    
+    pub fn with_session(config: Config, session: DownloadSession) -> Result<Self> {
+        let control = session.control.clone();
+        let downloader = Arc::new(Downloader::with_session(config.clone(), session)?);
+        let journal = PatchJournal::new(downloader.cache_dir());
+        let change_log = ChangeLog::new(downloader.cache_dir());
         let temp_dir = std::env::temp_dir().join("beam_patcher");
         std::fs::create_dir_all(&temp_dir)?;
         
         Ok(Patcher {
             config,
             downloader,
+            journal,
+            change_log,
             temp_dir,
+            events: EventSender::default(),
+            control,
         })
     }
     
+    // Which entries each applied patch added, replaced or removed.
+    pub fn change_log(&self) -> &ChangeLog {
+        &self.change_log
+    }
+    
+    // Pauses, resumes or cancels the patch run from another task.
+    pub fn control(&self) -> PatchControl {
+        self.control.clone()
+    }
+    
+    // Stream of progress events for everything this patcher does from now on.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<PatchEvent> {
+        self.events.subscribe()
+    }
+    
+    // Publishes a failure before handing the error back.
+    fn report<T>(&self, result: Result<T>) -> Result<T> {
+        match &result {
+            Err(Error::Cancelled) => self.events.emit(PatchEvent::Cancelled),
+            Err(e) => self.events.emit(PatchEvent::Failed { error: e.to_string() }),
+            Ok(_) => {}
+        }
+        result
+    }
+    
     pub async fn check_available_patches(&self) -> Result<usize> {
         info!("Checking for available patches");
         
@@ -39,103 +137,742 @@ impl Patcher {
     }
     
     pub async fn get_patch_list(&self) -> Result<Vec<PatchInfo>> {
-        self.downloader.download_patch_list().await
+        let result = match self.recover() {
+            Ok(_) => self.downloader.download_patch_list().await,
+            Err(e) => Err(e),
+        };
+        let patches = self.report(result)?;
+        
+        self.events.emit(PatchEvent::PatchListFetched {
+            count: patches.len(),
+            total_bytes: patches.iter().filter_map(|p| p.size).sum(),
+        });
+        
+        Ok(patches)
     }
     
-    pub async fn download_and_apply_patch<F>(
-        &self,
-        patch: &PatchInfo,
-        progress_callback: F,
-    ) -> Result<()>
-    where
-        F: FnMut(u64, u64) + Send + 'static,
-    {
-        let patch_path = self.temp_dir.join(&patch.filename);
+    // Downloads and applies a single patch, reporting progress as events.
+    pub async fn download_and_apply_patch(&self, patch: &PatchInfo) -> Result<()> {
+        self.apply_patches(vec![patch.clone()]).await
+    }
+    
+    pub async fn run_full_patch(&self) -> Result<()> {
+        info!("Starting full patch process");
         
-        self.downloader
-            .download_file_with_progress(&patch.filename, &patch_path, progress_callback)
-            .await?;
+        let patches = self.get_patch_list().await?;
+        info!("Found {} patches to apply", patches.len());
         
-        if let Some(checksum) = &patch.checksum {
-            if !self.downloader.verify_checksum(&patch_path, checksum).await? {
-                return Err(Error::PatchFailed(format!(
-                    "Checksum mismatch for {}",
-                    patch.filename
-                )));
-            }
-        }
+        self.apply_patches(patches).await?;
         
-        self.apply_patch(&patch_path).await?;
+        info!("All patches applied successfully");
         
-        self.downloader.mark_patch_applied(&patch.filename)?;
+        let result = self.install_client_info().await;
+        self.report(result)?;
         
-        tokio::fs::remove_file(&patch_path).await?;
+        Ok(())
+    }
+    
+    // Downloads and applies patches in list order. Later patches are fetched
+    // while earlier ones are applied, up to the configured number of waiting
+    // files and disk space, so the network isn't idle during GRF rebuilds.
+    // Consecutive patches for the same GRF are merged and saved together, so
+    // a long list costs a few rebuilds instead of one per patch.
+    pub async fn apply_patches(&self, patches: Vec<PatchInfo>) -> Result<()> {
+        let result = self.run_patches(patches).await;
+        self.report(result)
+    }
+    
+    async fn run_patches(&self, patches: Vec<PatchInfo>) -> Result<()> {
+        let (mut prefetched, _producer) = self.spawn_prefetch(patches);
+        let mut batch: Option<GrfBatch> = None;
+        
+        // However the run stops early, what was merged so far is kept.
+        let (applied, skipped) = match self.apply_prefetched(&mut prefetched, &mut batch).await {
+            Ok(counts) => counts,
+            Err(e) => {
+                self.save_before_exit(batch.take());
+                return Err(e);
+            }
+        };
         
+        let applied = applied + self.commit_batch(batch)?;
+        self.events.emit(PatchEvent::Finished { applied, skipped });
         Ok(())
     }
     
-    pub async fn run_full_patch(&self) -> Result<()> {
-        info!("Starting full patch process");
+    // Applies the prefetched patches as they arrive, merging GRF patches into
+    // `batch`. Returns how many patches were applied and skipped; patches
+    // still in `batch` are left to the caller to save.
+    async fn apply_prefetched(
+        &self,
+        prefetched: &mut mpsc::Receiver<Prefetched>,
+        batch: &mut Option<GrfBatch>,
+    ) -> Result<(usize, usize)> {
+        // Keys of patches skipped in this run, so their dependents are skipped too.
+        let mut skipped_keys = HashSet::new();
+        let mut applied = 0;
+        let mut skipped = 0;
         
+        while let Some(item) = prefetched.recv().await {
+            let Prefetched { index, patch, path, result, .. } = item;
+            let filename = patch.filename.clone();
+            
+            // Safe point between patches.
+            self.control.checkpoint().await?;
+            
+            if let Some(reason) = self.skip_reason(&patch, &mut skipped_keys)? {
+                if result.is_ok() {
+                    tokio::fs::remove_file(&path).await?;
+                }
+                self.events.emit(PatchEvent::Skipped { index, filename, reason });
+                skipped += 1;
+                continue;
+            }
+            
+            // Save what was merged so far before moving on to another target.
+            let grf_path = self.batch_grf_path(patch.target.as_ref())?;
+            if let Some(open) = batch.as_ref() {
+                if grf_path.as_deref() != Some(open.grf.path()) || open.is_full(&self.config.patcher.batch) {
+                    applied += self.commit_batch(batch.take())?;
+                }
+            }
+            
+            self.events.emit(PatchEvent::Applying { index, filename: filename.clone() });
+            
+            let batched = grf_path.is_some();
+            let result = match (result, grf_path) {
+                (Err(e), _) => Err(e),
+                (Ok(_), None) => self.install_downloaded_patch(index, &patch, &path).await,
+                (Ok(_), Some(grf_path)) => {
+                    let open = match batch {
+                        Some(open) => open,
+                        None => batch.insert(GrfBatch {
+                            grf: self.open_or_create_grf(&grf_path)?,
+                            patches: Vec::new(),
+                            change_log: Vec::new(),
+                        }),
+                    };
+                    self.merge_patch(open, index, &patch, &path).await
+                }
+            };
+            
+            match result {
+                // Batched patches are reported once their GRF is saved.
+                Ok(()) if batched => {}
+                Ok(()) => {
+                    self.events.emit(PatchEvent::Applied { index, filename });
+                    applied += 1;
+                }
+                Err(e) => {
+                    let reason = e.to_string();
+                    self.tolerate_optional(&patch, Err(e), &mut skipped_keys)?;
+                    self.events.emit(PatchEvent::Skipped { index, filename, reason });
+                    skipped += 1;
+                }
+            }
+        }
+        
+        Ok((applied, skipped))
+    }
+    
+    // Works out what applying the pending patches would change without
+    // touching the client: each patch is downloaded to the temp folder, read,
+    // compared with the current GRFs and files, and deleted again. Planning
+    // publishes download progress only and doesn't affect later runs.
+    pub async fn plan(&self) -> Result<PatchPlan> {
+        let result = self.build_plan().await;
+        self.report(result)
+    }
+    
+    async fn build_plan(&self) -> Result<PatchPlan> {
         let patches = self.downloader.download_patch_list().await?;
-        info!("Found {} patches to apply", patches.len());
+        let (mut prefetched, _producer) = self.spawn_prefetch(patches);
+        let mut skipped_keys = HashSet::new();
+        
+        let mut planned = Vec::new();
+        let mut targets: BTreeMap<String, (Baseline, TargetChanges)> = BTreeMap::new();
         
-        for (idx, patch) in patches.iter().enumerate() {
-            info!("Processing patch {}/{}: {}", idx + 1, patches.len(), patch.filename);
+        while let Some(item) = prefetched.recv().await {
+            let Prefetched { patch, path, result, .. } = item;
+            let target = self.target_name(patch.target.as_ref());
+            let mut entry = PlannedPatch {
+                filename: patch.filename.clone(),
+                target: target.clone(),
+                size: patch.size,
+                skipped: None,
+            };
             
-            let patch_path = self.temp_dir.join(&patch.filename);
+            if let Some(reason) = self.skip_reason(&patch, &mut skipped_keys)? {
+                if result.is_ok() {
+                    tokio::fs::remove_file(&path).await?;
+                }
+                entry.skipped = Some(reason);
+                planned.push(entry);
+                continue;
+            }
             
-            self.downloader
-                .download_file(&patch.filename, &patch_path)
-                .await?;
+            let changes = match result {
+                Ok(_) => {
+                    let changes = self.read_changes(&path).await;
+                    tokio::fs::remove_file(&path).await?;
+                    changes
+                }
+                Err(e) => Err(e),
+            };
+            let changes = match changes {
+                Ok(changes) => changes,
+                Err(e) => {
+                    let reason = e.to_string();
+                    self.tolerate_optional(&patch, Err(e), &mut skipped_keys)?;
+                    entry.skipped = Some(reason);
+                    planned.push(entry);
+                    continue;
+                }
+            };
             
-            if let Some(checksum) = &patch.checksum {
-                if !self.downloader.verify_checksum(&patch_path, checksum).await? {
-                    return Err(Error::PatchFailed(format!(
-                        "Checksum mismatch for {}",
-                        patch.filename
-                    )));
+            let (baseline, net) = match targets.entry(target) {
+                std::collections::btree_map::Entry::Occupied(existing) => existing.into_mut(),
+                std::collections::btree_map::Entry::Vacant(new) => {
+                    new.insert((self.baseline(patch.target.as_ref())?, TargetChanges::default()))
                 }
+            };
+            for (name, data) in &changes {
+                net.record(name, data.as_ref().map(|d| d.len() as u64), || baseline.size_of(name));
             }
             
-            self.apply_patch(&patch_path).await?;
-            
-            self.downloader.mark_patch_applied(&patch.filename)?;
+            planned.push(entry);
+        }
+        
+        Ok(PatchPlan {
+            patches: planned,
+            targets: targets
+                .into_iter()
+                .map(|(target, (_, net))| net.into_plan(target))
+                .collect(),
+        })
+    }
+    
+    // Current state of a target, opened read-only for planning.
+    fn baseline(&self, target: Option<&PatchTarget>) -> Result<Baseline> {
+        match self.batch_grf_path(target)? {
+            Some(grf_path) if grf_path.exists() => Ok(Baseline::Grf(Some(Grf::open(&grf_path)?))),
+            Some(_) => Ok(Baseline::Grf(None)),
+            None => Ok(Baseline::Directory(self.get_game_dir()?)),
+        }
+    }
+    
+    // Saves the patches merged before a run stopped early, so a failure or
+    // cancel later in the list doesn't throw away finished work.
+    fn save_before_exit(&self, batch: Option<GrfBatch>) {
+        if let Err(e) = self.commit_batch(batch) {
+            warn!("Could not save patches merged before stopping: {}", e);
+        }
+    }
+    
+    // GRF a patch is merged into, or None for loose-file patches.
+    fn batch_grf_path(&self, target: Option<&PatchTarget>) -> Result<Option<PathBuf>> {
+        match target {
+            Some(PatchTarget::Loose) => Ok(None),
+            Some(PatchTarget::Grf(name)) => Ok(Some(self.get_game_dir()?.join(name))),
+            None => Ok(Some(self.get_grf_path()?)),
+        }
+    }
+    
+    // Reads a downloaded patch into the batch's GRF and deletes the file, whose
+    // contents are now held in memory. The patch's changes are collected
+    // first, so a corrupt patch leaves the batch untouched, and merging them
+    // can't fail. A cancel is only honored before merging starts; once it has,
+    // the whole patch goes in, so the batch never holds half a patch.
+    async fn merge_patch(&self, batch: &mut GrfBatch, index: usize, patch: &PatchInfo, patch_path: &Path) -> Result<()> {
+        let changes = self.read_patch(index, patch_path).await?;
+        let logged = log_entries(&changes, |name| batch.grf.contains_file(name));
+        
+        self.control.checkpoint().await?;
+        
+        let grf = &mut batch.grf;
+        self.report_changes(index, changes, |name, data| {
+            match data {
+                Some(data) => grf.insert_file(name, data),
+                None => grf.remove_file(name),
+            }
+            Ok(())
+        })?;
+        
+        batch.patches.push((index, patch.clone(), patch_path.to_path_buf()));
+        batch.change_log.push(PatchChanges::new(
+            patch.filename.clone(),
+            patch.checksum.clone(),
+            self.target_name(patch.target.as_ref()),
+            logged,
+        ));
+        
+        // The patch is merged either way; a leftover file is only clutter.
+        if let Err(e) = tokio::fs::remove_file(patch_path).await {
+            warn!("Could not delete merged patch {:?}: {}", patch_path, e);
+        }
+        Ok(())
+    }
+    
+    // Saves the batch's GRF under one journal entry, then marks every merged
+    // patch as applied. Returns how many patches were saved.
+    fn commit_batch(&self, batch: Option<GrfBatch>) -> Result<usize> {
+        let Some(mut batch) = batch else {
+            return Ok(0);
+        };
+        let Some(((_, last, last_path), earlier)) = batch.patches.split_last() else {
+            return Ok(0);
+        };
+        let target = self.target_name(last.target.as_ref());
+        
+        let mut entry = JournalEntry::new(Some(last.clone()), last_path);
+        entry.merged = earlier.iter().map(|(_, patch, _)| patch.clone()).collect();
+        entry.change_log = batch.change_log.clone();
+        self.journal.begin(&entry)?;
+        
+        info!("Saving {} patch(es) into {:?}", batch.patches.len(), batch.grf.path());
+        if let Err(e) = self.save_grf(&mut batch.grf) {
+            self.abandon_journaled()?;
+            return Err(e);
+        }
+        
+        for (_, patch, _) in &batch.patches {
+            self.downloader.mark_patch_applied(patch, Some(&target))?;
+        }
+        self.log_changes(&batch.change_log);
+        self.journal.clear()?;
+        
+        let count = batch.patches.len();
+        self.events.emit(PatchEvent::Saved { target, patches: count });
+        for (index, patch, _) in batch.patches {
+            self.events.emit(PatchEvent::Applied { index, filename: patch.filename });
+        }
+        
+        Ok(count)
+    }
+    
+    // Starts downloading `patches` in order on a separate task. Each result is
+    // queued for the applying side; the bounded channel and the disk budget
+    // keep the task from getting too far ahead. Download progress is
+    // published as events.
+    fn spawn_prefetch(&self, patches: Vec<PatchInfo>) -> (mpsc::Receiver<Prefetched>, AbortOnDrop) {
+        let events = self.events.clone();
+        let limits = &self.config.patcher.prefetch;
+        let budget_kb = limits.max_mb.saturating_mul(1024).clamp(1, u32::MAX as u64) as u32;
+        let budget = Arc::new(Semaphore::new(budget_kb as usize));
+        let (tx, rx) = mpsc::channel(limits.max_patches.max(1));
+        
+        let downloader = self.downloader.clone();
+        let temp_dir = self.temp_dir.clone();
+        let run_total = patches.iter().filter_map(|p| p.size).sum();
+        let tracker = Arc::new(Mutex::new(ProgressTracker::new(run_total)));
+        
+        let handle = tokio::spawn(async move {
+            for (index, patch) in patches.into_iter().enumerate() {
+                // A patch bigger than the whole budget waits until it has the
+                // budget to itself.
+                let size_kb = patch.size.unwrap_or(0).div_ceil(1024).clamp(1, budget_kb as u64) as u32;
+                let Ok(permit) = budget.clone().acquire_many_owned(size_kb).await else {
+                    return;
+                };
+                
+                let path = temp_dir.join(&patch.filename);
+                let filename = patch.filename.clone();
+                events.emit(PatchEvent::DownloadStarted { index, filename: filename.clone(), size: patch.size });
+                
+                tracker.lock().unwrap().start_file(patch.size);
+                
+                let progress = events.clone();
+                let progress_name = filename.clone();
+                let progress_tracker = tracker.clone();
+                let result = downloader
+                    .download_patch(&patch, &path, move |downloaded, total| {
+                        let stats = progress_tracker.lock().unwrap().update(downloaded, total);
+                        progress.emit(PatchEvent::DownloadProgress {
+                            index,
+                            filename: progress_name.clone(),
+                            downloaded,
+                            total,
+                            stats,
+                        });
+                    })
+                    .await;
+                
+                tracker.lock().unwrap().finish_file(result.is_ok());
+                if result.is_ok() {
+                    events.emit(PatchEvent::DownloadFinished { index, filename });
+                }
+                
+                let item = Prefetched { index, patch, path, result, _permit: permit };
+                if tx.send(item).await.is_err() {
+                    return;
+                }
+            }
+        });
+        
+        (rx, AbortOnDrop(handle))
+    }
+    
+    // Checks the manifest requirements of a patch. Returns why it should be
+    // skipped: an optional patch needing a newer patcher, or any patch whose
+    // dependency is in `skipped`. Skipped patches are added to `skipped`.
+    fn skip_reason(&self, patch: &PatchInfo, skipped: &mut HashSet<String>) -> Result<Option<String>> {
+        if let Some(dependency) = patch.dependencies.iter().find(|d| skipped.contains(d.as_str())) {
+            let reason = format!("dependency {} was skipped", dependency);
+            warn!("Skipping {}: {}", patch.filename, reason);
+            skipped.insert(patch.key().to_string());
+            return Ok(Some(reason));
+        }
+        
+        if let Some(required) = &patch.min_patcher_version {
+            // The version this binary was built as; app.version in the config
+            // is only a display name and can be edited.
+            let current = env!("CARGO_PKG_VERSION");
+            let too_old = self_update::version::bump_is_greater(current, required)
+                .map_err(|e| Error::InvalidConfig(format!("Invalid patcher version: {}", e)))?;
             
-            tokio::fs::remove_file(&patch_path).await?;
+            if too_old {
+                if patch.optional {
+                    let reason = format!("needs patcher {} (running {})", required, current);
+                    warn!("Skipping optional patch {}: {}", patch.filename, reason);
+                    skipped.insert(patch.key().to_string());
+                    return Ok(Some(reason));
+                }
+                
+                return Err(Error::PatchFailed(format!(
+                    "Patch {} needs patcher version {} or newer (running {}), please update the patcher",
+                    patch.filename, required, current
+                )));
+            }
         }
         
-        info!("All patches applied successfully");
+        Ok(None)
+    }
+    
+    // Optional patches that fail to download or apply are skipped rather than
+    // aborting the run; the GRF is untouched since the failure was rolled back.
+    // A cancel always stops the run.
+    fn tolerate_optional(&self, patch: &PatchInfo, result: Result<()>, skipped: &mut HashSet<String>) -> Result<()> {
+        match result {
+            Err(e) if patch.optional && !matches!(e, Error::Cancelled) => {
+                warn!("Skipping optional patch {}: {}", patch.filename, e);
+                skipped.insert(patch.key().to_string());
+                Ok(())
+            }
+            other => other,
+        }
+    }
+    
+    // Finishes or undoes a patch interrupted by a crash or power loss, based on
+    // the last stage the journal recorded. Safe to call when nothing is pending.
+    pub fn recover(&self) -> Result<Option<RecoveryOutcome>> {
+        self.restore_legacy_backup()?;
+        
+        let Some(entry) = self.journal.load()? else {
+            return Ok(None);
+        };
+        
+        let name = entry
+            .patch
+            .as_ref()
+            .map(|p| p.filename.clone())
+            .unwrap_or_else(|| entry.patch_path.display().to_string());
+        
+        let outcome = match entry.stage {
+            JournalStage::Downloaded | JournalStage::Applying => {
+                warn!("Rolling back interrupted patch: {}", name);
+                self.discard_staged(&entry)?;
+                RecoveryOutcome::RolledBack(name)
+            }
+            JournalStage::Staged | JournalStage::Committed => {
+                warn!("Completing interrupted patch: {}", name);
+                
+                // A missing staging file at the Staged stage means the rename
+                // already went through before the journal caught up.
+                if entry.stage == JournalStage::Staged {
+                    if let (Some(staged), Some(grf_path)) = (&entry.staged_path, &entry.grf_path) {
+                        if staged.exists() {
+                            std::fs::rename(staged, grf_path)?;
+                        }
+                    }
+                    replace_loose_files(&entry.loose_files)?;
+                }
+                
+                for patch in entry.merged.iter().chain(&entry.patch) {
+                    self.downloader.mark_patch_applied(patch, Some(&self.target_name(patch.target.as_ref())))?;
+                }
+                // The records may have been written just before the crash.
+                if let Err(e) = self.change_log.append_missing(&entry.change_log) {
+                    warn!("Could not write the change log: {}", e);
+                }
+                
+                if entry.patch.is_some() && entry.patch_path.exists() {
+                    std::fs::remove_file(&entry.patch_path)?;
+                }
+                
+                RecoveryOutcome::RolledForward(name)
+            }
+        };
+        
+        self.journal.clear()?;
+        Ok(Some(outcome))
+    }
+    
+    // GRFs saved by older versions were moved to .grf.bak while being rebuilt;
+    // put the untouched original back if a crash left only the backup.
+    fn restore_legacy_backup(&self) -> Result<()> {
+        let grf_path = self.get_grf_path()?;
+        let backup_path = grf_path.with_extension("grf.bak");
+        
+        if !grf_path.exists() && backup_path.exists() {
+            warn!("Restoring GRF from interrupted save: {:?}", backup_path);
+            std::fs::rename(&backup_path, &grf_path)?;
+        }
+        
+        Ok(())
+    }
+    
+    // Cleans up after a journaled write that failed before the GRF was
+    // replaced, leaving the original as it was.
+    fn abandon_journaled(&self) -> Result<()> {
+        if let Some(entry) = self.journal.load()? {
+            if entry.stage != JournalStage::Committed {
+                self.discard_staged(&entry)?;
+                self.journal.clear()?;
+            }
+        }
+        Ok(())
+    }
+    
+    fn discard_staged(&self, entry: &JournalEntry) -> Result<()> {
+        let loose = entry.loose_files.iter().filter_map(|file| file.staged.as_ref());
+        for staged in entry.staged_path.iter().chain(loose) {
+            if staged.exists() {
+                std::fs::remove_file(staged)?;
+            }
+        }
+        Ok(())
+    }
+    
+    // The patch file has already been checked against its checksum by
+    // Downloader::download_patch.
+    async fn install_downloaded_patch(&self, index: usize, patch: &PatchInfo, patch_path: &Path) -> Result<()> {
+        let changes = self.apply_journaled(index, Some(patch.clone()), patch_path).await?;
+        
+        self.downloader.mark_patch_applied(patch, Some(&self.target_name(patch.target.as_ref())))?;
+        self.log_changes(&[changes]);
+        self.journal.clear()?;
+        
+        tokio::fs::remove_file(patch_path).await?;
+        
         Ok(())
     }
     
+    // Applies a patch under the journal. On failure before the GRF or loose
+    // files are replaced the staging files are removed and the originals stay
+    // as they were.
+    // Returns the change log record, for the caller to write once the patch
+    // is recorded as applied.
+    async fn apply_journaled(&self, index: usize, patch: Option<PatchInfo>, patch_path: &Path) -> Result<PatchChanges> {
+        let target = patch.as_ref().and_then(|p| p.target.clone());
+        let changes = self.read_patch(index, patch_path).await?;
+        
+        let baseline = self.baseline(target.as_ref())?;
+        let filename = match &patch {
+            Some(patch) => patch.filename.clone(),
+            None => patch_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        };
+        let record = PatchChanges::new(
+            filename,
+            patch.as_ref().and_then(|p| p.checksum.clone()),
+            self.target_name(target.as_ref()),
+            log_entries(&changes, |name| baseline.size_of(name).is_some()),
+        );
+        drop(baseline);
+        
+        let mut entry = JournalEntry::new(patch, patch_path);
+        entry.change_log = vec![record.clone()];
+        self.journal.begin(&entry)?;
+        
+        if let Err(e) = self.write_to_target(index, patch_path, changes, target.as_ref()).await {
+            self.abandon_journaled()?;
+            return Err(e);
+        }
+        
+        Ok(record)
+    }
+    
+    // The patches are applied either way, so a change log that can't be
+    // written only warns.
+    fn log_changes(&self, records: &[PatchChanges]) {
+        if let Err(e) = self.change_log.append(records) {
+            warn!("Could not write the change log: {}", e);
+        }
+    }
+    
+    // Writes the GRF's pending changes, recording each step in the journal so
+    // the mark-as-applied only ever follows a durable GRF write.
+    fn save_grf(&self, grf: &mut Grf) -> Result<()> {
+        self.journal.save_grf(grf)
+    }
+    
     pub async fn apply_patch(&self, patch_path: &Path) -> Result<()> {
+        self.apply_patch_to(patch_path, None).await
+    }
+    
+    // Applies a patch to the given target, or to the configured GRF if None.
+    pub async fn apply_patch_to(&self, patch_path: &Path, target: Option<&PatchTarget>) -> Result<()> {
+        self.apply_indexed(0, patch_path, target).await
+    }
+    
+    // `index` identifies the patch in the events published while applying it.
+    async fn apply_indexed(&self, index: usize, patch_path: &Path, target: Option<&PatchTarget>) -> Result<()> {
+        let changes = self.read_patch(index, patch_path).await?;
+        self.write_to_target(index, patch_path, changes, target).await
+    }
+    
+    async fn write_to_target(
+        &self,
+        index: usize,
+        patch_path: &Path,
+        changes: Vec<Change>,
+        target: Option<&PatchTarget>,
+    ) -> Result<()> {
+        info!("Applying patch: {:?} (target: {})", patch_path, self.target_name(target));
+        
+        let mut loose_files = Vec::new();
+        let mut sink = match self.batch_grf_path(target)? {
+            Some(grf_path) => PatchSink::Grf(self.open_or_create_grf(&grf_path)?),
+            None => {
+                let root = self.get_game_dir()?;
+                loose_files = changes
+                    .iter()
+                    .map(|(name, data)| {
+                        let path = loose_path(&root, name)?;
+                        Ok(match data {
+                            Some(_) => LooseFile::write(path),
+                            None => LooseFile::remove(path),
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                
+                self.journal.stage_loose(&loose_files)?;
+                PatchSink::Directory(root)
+            }
+        };
+        
+        self.write_changes(index, changes, |name, data| match data {
+            Some(data) => sink.write(name, data),
+            None => sink.remove(name),
+        })
+        .await?;
+        
+        match &mut sink {
+            PatchSink::Grf(grf) => {
+                info!("Saving GRF file table...");
+                self.save_grf(grf)?;
+                self.events.emit(PatchEvent::Saved { target: self.target_name(target), patches: 1 });
+            }
+            PatchSink::Directory(_) => self.commit_loose(&loose_files)?,
+            PatchSink::Changes(_) => {}
+        }
+        
+        Ok(())
+    }
+    
+    // Renames the staged loose files over their targets and removes the
+    // deleted ones, once every file of the patch is staged.
+    fn commit_loose(&self, files: &[LooseFile]) -> Result<()> {
+        self.journal.advance(JournalStage::Staged, None, None)?;
+        replace_loose_files(files)?;
+        self.journal.advance(JournalStage::Committed, None, None)?;
+        Ok(())
+    }
+    
+    // Opens and checks a patch file and collects its changes, so nothing is
+    // written unless the whole patch reads cleanly.
+    async fn read_patch(&self, index: usize, patch_path: &Path) -> Result<Vec<Change>> {
+        let filename = patch_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.events.emit(PatchEvent::Verifying { index, filename });
+        
+        self.read_changes(patch_path).await
+    }
+    
+    // Collects a patch file's changes without publishing anything.
+    async fn read_changes(&self, patch_path: &Path) -> Result<Vec<Change>> {
         let extension = patch_path
             .extension()
             .and_then(|s| s.to_str())
-            .unwrap_or("");
-        
-        info!("Applying patch: {:?} (type: {})", patch_path, extension);
+            .unwrap_or("")
+            .to_lowercase();
         
-        match extension.to_lowercase().as_str() {
-            "beam" => self.apply_beam_patch(patch_path).await,
-            "thor" => self.apply_thor_patch(patch_path).await,
-            "rgz" => self.apply_rgz_patch(patch_path).await,
-            "gpf" => self.apply_gpf_patch(patch_path).await,
+        let mut sink = PatchSink::Changes(Vec::new());
+        match extension.as_str() {
+            "beam" => self.apply_beam_patch(patch_path, &mut sink).await?,
+            "thor" => self.apply_thor_patch(patch_path, &mut sink).await?,
+            "rgz" => self.apply_rgz_patch(patch_path, &mut sink).await?,
+            "gpf" => self.apply_gpf_patch(patch_path, &mut sink).await?,
             _ => {
                 warn!("Unknown patch format: {}", extension);
-                Err(Error::PatchFailed(format!("Unknown patch format: {}", extension)))
+                return Err(Error::PatchFailed(format!("Unknown patch format: {}", extension)));
             }
         }
+        
+        match sink {
+            PatchSink::Changes(changes) => Ok(changes),
+            _ => Ok(Vec::new()),
+        }
+    }
+    
+    // Hands each collected change to `write`, reporting it as an entry. Pause
+    // and cancel are honored between entries.
+    async fn write_changes<W>(&self, index: usize, changes: Vec<Change>, mut write: W) -> Result<()>
+    where
+        W: FnMut(&str, Option<Vec<u8>>) -> Result<()>,
+    {
+        let entries = changes.len();
+        
+        for (entry, (name, data)) in changes.into_iter().enumerate() {
+            self.control.checkpoint().await?;
+            self.events.emit(PatchEvent::ApplyingEntry { index, entry: entry + 1, entries, name: name.clone() });
+            write(&name, data)?;
+        }
+        
+        Ok(())
+    }
+    
+    // Like `write_changes`, but without stopping between entries.
+    fn report_changes<W>(&self, index: usize, changes: Vec<Change>, mut write: W) -> Result<()>
+    where
+        W: FnMut(&str, Option<Vec<u8>>) -> Result<()>,
+    {
+        let entries = changes.len();
+        
+        for (entry, (name, data)) in changes.into_iter().enumerate() {
+            self.events.emit(PatchEvent::ApplyingEntry { index, entry: entry + 1, entries, name: name.clone() });
+            write(&name, data)?;
+        }
+        
+        Ok(())
     }
     
-    async fn apply_beam_patch(&self, patch_path: &Path) -> Result<()> {
+    fn target_name(&self, target: Option<&PatchTarget>) -> String {
+        match target {
+            Some(PatchTarget::Grf(name)) => name.clone(),
+            Some(PatchTarget::Loose) => "loose files".to_string(),
+            None => self.config.patcher.target_grf.clone(),
+        }
+    }
+    
+    async fn apply_beam_patch(&self, patch_path: &Path, sink: &mut PatchSink) -> Result<()> {
         info!("Applying BEAM patch with MD5 verification");
         let beam = BeamArchive::open(patch_path)?;
         
-        let grf_path = self.get_grf_path()?;
-        let mut grf = self.open_or_create_grf(&grf_path)?;
-        
         for filename in beam.list_files() {
             info!("Extracting and verifying: {}", filename);
             
@@ -154,51 +891,40 @@ impl Patcher {
             let grf_filename = entry.grf_path.as_ref().unwrap_or(&entry.filename);
             
             info!("Patching file: {} -> {} ({} bytes)", filename, grf_filename, data.len());
-            grf.patch_file(grf_filename, &data)?;
+            sink.write(grf_filename, data)?;
         }
         
-        info!("Saving GRF file table...");
-        grf.save()?;
-        
         info!("BEAM patch applied successfully with all checksums verified");
         Ok(())
     }
     
-    async fn apply_thor_patch(&self, patch_path: &Path) -> Result<()> {
+    async fn apply_thor_patch(&self, patch_path: &Path, sink: &mut PatchSink) -> Result<()> {
         let thor = Thor::open(patch_path)?;
         
-        let grf_path = self.get_grf_path()?;
-        let mut grf = self.open_or_create_grf(&grf_path)?;
-        
-        for entry in thor.get_entries() {
+        // The entries are moved into the sink, so the patch's data is held once.
+        for entry in thor.entries {
             match entry {
                 beam_formats::thor::ThorEntry::Add { filename, data } => {
                     info!("Adding/updating file: {}", filename);
-                    grf.patch_file(filename, data)?;
+                    sink.write(&filename, data)?;
                 }
                 beam_formats::thor::ThorEntry::Remove { filename } => {
-                    info!("Removing file: {}", filename);
+                    sink.remove(&filename)?;
                 }
             }
         }
         
-        info!("Saving GRF file table...");
-        grf.save()?;
-        
         Ok(())
     }
     
-    async fn apply_rgz_patch(&self, patch_path: &Path) -> Result<()> {
+    async fn apply_rgz_patch(&self, patch_path: &Path, sink: &mut PatchSink) -> Result<()> {
         let rgz = Rgz::open(patch_path)?;
         
-        let grf_path = self.get_grf_path()?;
-        let mut grf = self.open_or_create_grf(&grf_path)?;
-        
-        for entry in rgz.get_entries() {
+        for entry in rgz.entries {
             match entry {
                 beam_formats::rgz::RgzEntry::File { name, data } => {
                     info!("Adding file: {}", name);
-                    grf.patch_file(name, data)?;
+                    sink.write(&name, data)?;
                 }
                 beam_formats::rgz::RgzEntry::Directory { name } => {
                     debug!("Creating directory: {}", name);
@@ -206,27 +932,18 @@ impl Patcher {
             }
         }
         
-        info!("Saving GRF file table...");
-        grf.save()?;
-        
         Ok(())
     }
     
-    async fn apply_gpf_patch(&self, patch_path: &Path) -> Result<()> {
+    async fn apply_gpf_patch(&self, patch_path: &Path, sink: &mut PatchSink) -> Result<()> {
         let gpf = Gpf::open(patch_path)?;
         
-        let grf_path = self.get_grf_path()?;
-        let mut grf = self.open_or_create_grf(&grf_path)?;
-        
         for filename in gpf.list_files() {
             info!("Patching file: {}", filename);
             let data = gpf.extract_file(filename)?;
-            grf.patch_file(filename, &data)?;
+            sink.write(filename, data)?;
         }
         
-        info!("Saving GRF file table...");
-        grf.save()?;
-        
         Ok(())
     }
     
@@ -238,21 +955,73 @@ impl Patcher {
         }
         
         info!("Applying manual patch: {:?}", patch_path);
-        self.apply_patch(patch_path).await
+        
+        self.recover()?;
+        let result = self.apply_journaled(0, None, patch_path).await;
+        let changes = self.report(result)?;
+        self.log_changes(&[changes]);
+        self.journal.clear()?;
+        
+        Ok(())
     }
     
-    fn get_grf_path(&self) -> Result<PathBuf> {
-        let grf_filename = &self.config.patcher.target_grf;
+    // Writes the clientinfo.xml rendered from the server config, either into the
+    // target GRF or the game's data folder. Runs after patching so a patch that
+    // ships its own clientinfo can't point players at the wrong server.
+    pub async fn install_client_info(&self) -> Result<Option<PathBuf>> {
+        let Some(client_info) = ClientInfo::from_config(&self.config)? else {
+            return Ok(None);
+        };
+        
+        let xml = client_info.render();
         
-        if let Some(game_dir) = &self.config.app.game_directory {
-            let path = Path::new(game_dir).join(grf_filename);
-            Ok(path)
+        if client_info.write_to_grf {
+            let grf_path = self.get_grf_path()?;
+            let mut grf = self.open_or_create_grf(&grf_path)?;
+            
+            let inner_path = client_info.grf_path();
+            if let Ok(existing) = grf.extract_file(&inner_path) {
+                if existing == xml.as_bytes() {
+                    debug!("{} is already up to date", inner_path);
+                    return Ok(Some(grf_path));
+                }
+            }
+            
+            info!("Writing {} into {:?}", inner_path, grf_path);
+            grf.patch_file(&inner_path, xml.as_bytes())?;
+            
+            // Journaled like a patch, so a crash during the save is recovered.
+            self.journal.begin(&JournalEntry::new(None, Path::new(&client_info.file_name)))?;
+            if let Err(e) = self.save_grf(&mut grf) {
+                self.abandon_journaled()?;
+                return Err(e);
+            }
+            self.journal.clear()?;
+            
+            Ok(Some(grf_path))
         } else {
-            let exe_dir = crate::get_executable_dir()?;
-            Ok(exe_dir.join(grf_filename))
+            let data_dir = self.get_game_dir()?.join("data");
+            tokio::fs::create_dir_all(&data_dir).await?;
+            
+            let path = data_dir.join(&client_info.file_name);
+            info!("Writing {:?}", path);
+            tokio::fs::write(&path, xml).await?;
+            
+            Ok(Some(path))
+        }
+    }
+    
+    fn get_game_dir(&self) -> Result<PathBuf> {
+        match &self.config.app.game_directory {
+            Some(game_dir) => Ok(PathBuf::from(game_dir)),
+            None => crate::get_executable_dir(),
         }
     }
     
+    fn get_grf_path(&self) -> Result<PathBuf> {
+        Ok(self.get_game_dir()?.join(&self.config.patcher.target_grf))
+    }
+    
     fn open_or_create_grf(&self, path: &Path) -> Result<Grf> {
         if path.exists() {
             info!("Opening existing GRF: {:?}", path);
@@ -263,3 +1032,298 @@ impl Patcher {
         }
     }
 }
+
+// What a target holds before patching; a GRF that doesn't exist yet is None.
+enum Baseline {
+    Grf(Option<Grf>),
+    Directory(PathBuf),
+}
+
+impl Baseline {
+    // Uncompressed size of a file, if it exists.
+    fn size_of(&self, name: &str) -> Option<u64> {
+        match self {
+            Baseline::Grf(grf) => grf
+                .as_ref()
+                .and_then(|grf| grf.get_entry(name))
+                .map(|entry| entry.uncompressed_size as u64),
+            Baseline::Directory(root) => loose_path(root, name)
+                .ok()
+                .and_then(|path| std::fs::metadata(path).ok())
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len()),
+        }
+    }
+}
+
+// A file a patch adds or replaces, or removes when the data is None.
+type Change = (String, Option<Vec<u8>>);
+
+// Change log entries for a patch's changes. Removing a file the target
+// doesn't have changes nothing, so it's left out.
+fn log_entries<F>(changes: &[Change], exists: F) -> Vec<EntryChange>
+where
+    F: Fn(&str) -> bool,
+{
+    changes
+        .iter()
+        .filter_map(|(name, data)| {
+            let existed = exists(name);
+            (data.is_some() || existed).then(|| EntryChange::new(name, data.as_deref(), existed))
+        })
+        .collect()
+}
+
+// Where a patch's files end up: inside a GRF, as loose files under a
+// directory, or collected as changes to be written later. Loose files are
+// only staged here; `Patcher::commit_loose` moves them into place.
+enum PatchSink {
+    Grf(Grf),
+    Directory(PathBuf),
+    Changes(Vec<Change>),
+}
+
+impl PatchSink {
+    // Takes the data by value so it is moved, not copied, into a GRF or the
+    // collected changes.
+    fn write(&mut self, name: &str, data: Vec<u8>) -> Result<()> {
+        match self {
+            PatchSink::Grf(grf) => {
+                grf.insert_file(name, data);
+            }
+            PatchSink::Changes(changes) => {
+                changes.push((name.to_string(), Some(data)));
+            }
+            PatchSink::Directory(root) => {
+                let file = LooseFile::write(loose_path(root, name)?);
+                let Some(staged) = &file.staged else {
+                    return Ok(());
+                };
+                debug!("Staging loose file: {:?}", staged);
+                if let Some(parent) = staged.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                crate::write_file_synced(staged, &data)?;
+            }
+        }
+        Ok(())
+    }
+    
+    fn remove(&mut self, name: &str) -> Result<()> {
+        match self {
+            PatchSink::Grf(grf) => {
+                info!("Removing file: {}", name);
+                grf.remove_file(name);
+            }
+            PatchSink::Changes(changes) => {
+                changes.push((name.to_string(), None));
+            }
+            // Removals wait for the commit, so a rolled back patch keeps the file.
+            PatchSink::Directory(_) => {}
+        }
+        Ok(())
+    }
+}
+
+// Moves staged loose files into place. Only the last change to each path
+// counts, and files already moved are skipped, so this can be repeated after
+// a crash.
+fn replace_loose_files(files: &[LooseFile]) -> Result<()> {
+    let mut seen = HashSet::new();
+    
+    for file in files.iter().rev() {
+        if !seen.insert(&file.path) {
+            continue;
+        }
+        
+        match &file.staged {
+            Some(staged) => {
+                if staged.exists() {
+                    debug!("Replacing loose file: {:?}", file.path);
+                    std::fs::rename(staged, &file.path)?;
+                }
+            }
+            None => {
+                // An earlier change in the same patch may have staged the file.
+                let staged = LooseFile::write(file.path.clone()).staged;
+                for path in staged.iter().chain([&file.path]) {
+                    if path.exists() {
+                        info!("Removing loose file: {:?}", path);
+                        std::fs::remove_file(path)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Maps a GRF-style path (backslash separated) under the game directory,
+// refusing anything that would escape it.
+fn loose_path(root: &Path, name: &str) -> Result<PathBuf> {
+    let relative = PathBuf::from(name.replace('\\', "/"));
+    
+    if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return Err(Error::PatchFailed(format!("Refusing to write outside the game directory: {}", name)));
+    }
+    
+    Ok(root.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn patcher(game_dir: &Path) -> Patcher {
+        let mut config = Config::default();
+        config.app.game_directory = Some(game_dir.to_string_lossy().to_string());
+        Patcher::new(config).unwrap()
+    }
+    
+    fn loose_patch(patcher: &Patcher, stage: JournalStage, files: Vec<LooseFile>) -> PatchInfo {
+        let patch = PatchInfo {
+            filename: "loose.thor".to_string(),
+            target: Some(PatchTarget::Loose),
+            ..PatchInfo::default()
+        };
+        let patch_path = patcher.downloader.cache_dir().join("loose.thor");
+        std::fs::write(&patch_path, b"patch").unwrap();
+        
+        let mut entry = JournalEntry::new(Some(patch.clone()), &patch_path);
+        entry.loose_files = files;
+        entry.stage = stage;
+        patcher.journal.begin(&entry).unwrap();
+        
+        patch
+    }
+    
+    #[test]
+    fn skip_reason_compares_with_the_built_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut patcher = patcher(dir.path());
+        patcher.config.app.version = "0.0.1".to_string();
+        let mut skipped = HashSet::new();
+        
+        let supported = PatchInfo {
+            filename: "supported.thor".to_string(),
+            min_patcher_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            ..PatchInfo::default()
+        };
+        assert_eq!(patcher.skip_reason(&supported, &mut skipped).unwrap(), None);
+        
+        let mut newer = PatchInfo {
+            filename: "newer.thor".to_string(),
+            id: Some("newer".to_string()),
+            min_patcher_version: Some("999.0.0".to_string()),
+            optional: true,
+            ..PatchInfo::default()
+        };
+        assert!(patcher.skip_reason(&newer, &mut skipped).unwrap().is_some());
+        assert!(skipped.contains("newer"));
+        
+        let dependent = PatchInfo {
+            filename: "dependent.thor".to_string(),
+            dependencies: vec!["newer".to_string()],
+            ..PatchInfo::default()
+        };
+        assert!(patcher.skip_reason(&dependent, &mut skipped).unwrap().is_some());
+        assert!(skipped.contains("dependent.thor"));
+        
+        newer.optional = false;
+        assert!(matches!(patcher.skip_reason(&newer, &mut HashSet::new()), Err(Error::PatchFailed(_))));
+    }
+    
+    #[test]
+    fn recover_without_journal_does_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let patcher = patcher(dir.path());
+        
+        assert_eq!(patcher.recover().unwrap(), None);
+    }
+    
+    #[test]
+    fn recover_rolls_back_loose_files_still_being_staged() {
+        let dir = tempfile::tempdir().unwrap();
+        let patcher = patcher(dir.path());
+        let kept = dir.path().join("kept.txt");
+        let removed = dir.path().join("removed.txt");
+        std::fs::write(&kept, b"old").unwrap();
+        std::fs::write(&removed, b"old").unwrap();
+        
+        let write = LooseFile::write(kept.clone());
+        std::fs::write(write.staged.as_ref().unwrap(), b"new").unwrap();
+        let patch = loose_patch(&patcher, JournalStage::Applying, vec![write.clone(), LooseFile::remove(removed.clone())]);
+        
+        assert_eq!(patcher.recover().unwrap(), Some(RecoveryOutcome::RolledBack(patch.filename.clone())));
+        assert_eq!(std::fs::read(&kept).unwrap(), b"old");
+        assert!(!write.staged.unwrap().exists());
+        assert!(removed.exists());
+        assert!(patcher.journal.load().unwrap().is_none());
+        assert!(patcher.downloader.patch_state().get(&patch.filename).unwrap().is_none());
+    }
+    
+    #[test]
+    fn recover_rolls_forward_staged_loose_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let patcher = patcher(dir.path());
+        let replaced = dir.path().join("replaced.txt");
+        let removed = dir.path().join("removed.txt");
+        std::fs::write(&replaced, b"old").unwrap();
+        std::fs::write(&removed, b"old").unwrap();
+        
+        let write = LooseFile::write(replaced.clone());
+        std::fs::write(write.staged.as_ref().unwrap(), b"new").unwrap();
+        let patch = loose_patch(&patcher, JournalStage::Staged, vec![write.clone(), LooseFile::remove(removed.clone())]);
+        
+        assert_eq!(patcher.recover().unwrap(), Some(RecoveryOutcome::RolledForward(patch.filename.clone())));
+        assert_eq!(std::fs::read(&replaced).unwrap(), b"new");
+        assert!(!write.staged.unwrap().exists());
+        assert!(!removed.exists());
+        assert!(patcher.journal.load().unwrap().is_none());
+        assert!(patcher.downloader.patch_state().get(&patch.filename).unwrap().is_some());
+        assert!(!patcher.downloader.cache_dir().join("loose.thor").exists());
+    }
+    
+    #[test]
+    fn recover_rolls_forward_staged_grf() {
+        let dir = tempfile::tempdir().unwrap();
+        let patcher = patcher(dir.path());
+        let grf_path = dir.path().join("data.grf");
+        let staged_path = dir.path().join("data.grf.new");
+        std::fs::write(&grf_path, b"old").unwrap();
+        std::fs::write(&staged_path, b"new").unwrap();
+        
+        let patch_path = patcher.downloader.cache_dir().join("update.thor");
+        let mut entry = JournalEntry::new(None, &patch_path);
+        entry.grf_path = Some(grf_path.clone());
+        entry.staged_path = Some(staged_path.clone());
+        entry.stage = JournalStage::Staged;
+        patcher.journal.begin(&entry).unwrap();
+        
+        assert!(matches!(patcher.recover().unwrap(), Some(RecoveryOutcome::RolledForward(_))));
+        assert_eq!(std::fs::read(&grf_path).unwrap(), b"new");
+        assert!(!staged_path.exists());
+    }
+    
+    #[test]
+    fn replacing_loose_files_again_keeps_the_last_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, b"old").unwrap();
+        
+        let write = LooseFile::write(path.clone());
+        std::fs::write(write.staged.as_ref().unwrap(), b"new").unwrap();
+        let files = vec![LooseFile::remove(path.clone()), write];
+        
+        replace_loose_files(&files).unwrap();
+        replace_loose_files(&files).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        
+        // Written then removed by the same patch: the staged copy goes too.
+        let write = LooseFile::write(path.clone());
+        std::fs::write(write.staged.as_ref().unwrap(), b"newer").unwrap();
+        replace_loose_files(&[write.clone(), LooseFile::remove(path.clone())]).unwrap();
+        assert!(!path.exists());
+        assert!(!write.staged.unwrap().exists());
+    }
+}
@@ -0,0 +1,97 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// What a patch run would do to the client, produced by `Patcher::plan`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PatchPlan {
+    pub patches: Vec<PlannedPatch>,
+    pub targets: Vec<TargetPlan>,
+}
+
+impl PatchPlan {
+    pub fn is_empty(&self) -> bool {
+        self.targets.iter().all(TargetPlan::is_empty)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedPatch {
+    pub filename: String,
+    pub target: String,
+    pub size: Option<u64>,
+    // Why the run would skip this patch.
+    pub skipped: Option<String>,
+}
+
+// Net changes to one GRF (or to the loose files), after all patches.
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetPlan {
+    pub target: String,
+    pub added: Vec<FileChange>,
+    pub replaced: Vec<FileChange>,
+    pub removed: Vec<FileChange>,
+    // Change in total uncompressed size, in bytes.
+    pub size_delta: i64,
+}
+
+impl TargetPlan {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.replaced.is_empty() && self.removed.is_empty()
+    }
+}
+
+// Sizes are uncompressed; None means the file doesn't exist on that side.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileChange {
+    pub name: String,
+    pub old_size: Option<u64>,
+    pub new_size: Option<u64>,
+}
+
+// Folds the changes of consecutive patches into their net effect on a target.
+#[derive(Debug, Default)]
+pub(crate) struct TargetChanges {
+    // File name -> (size before the run, size after the patches so far).
+    files: BTreeMap<String, (Option<u64>, Option<u64>)>,
+}
+
+impl TargetChanges {
+    // `current_size` is only asked the first time a file comes up.
+    pub(crate) fn record<F>(&mut self, name: &str, new_size: Option<u64>, current_size: F)
+    where
+        F: FnOnce() -> Option<u64>,
+    {
+        self.files
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                let size = current_size();
+                (size, size)
+            })
+            .1 = new_size;
+    }
+    
+    pub(crate) fn into_plan(self, target: String) -> TargetPlan {
+        let mut plan = TargetPlan {
+            target,
+            added: Vec::new(),
+            replaced: Vec::new(),
+            removed: Vec::new(),
+            size_delta: 0,
+        };
+        
+        for (name, (old_size, new_size)) in self.files {
+            plan.size_delta += new_size.unwrap_or(0) as i64 - old_size.unwrap_or(0) as i64;
+            
+            let list = match (old_size, new_size) {
+                (None, Some(_)) => &mut plan.added,
+                (Some(_), Some(_)) => &mut plan.replaced,
+                (Some(_), None) => &mut plan.removed,
+                // Added and removed again within the run.
+                (None, None) => continue,
+            };
+            list.push(FileChange { name, old_size, new_size });
+        }
+        
+        plan
+    }
+}
@@ -0,0 +1,185 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Speed is averaged over this much recent time, long enough to even out the
+// bursts the rate limiter and chunked downloads produce.
+const WINDOW: Duration = Duration::from_secs(5);
+// No speed is reported until this much has been measured.
+const MIN_SPAN: Duration = Duration::from_millis(500);
+const SAMPLE_STEP: Duration = Duration::from_millis(100);
+
+// Download speed and time remaining, for the current file and the whole run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TransferStats {
+    pub bytes_per_sec: u64,
+    pub eta_secs: Option<u64>,
+    pub run_downloaded: u64,
+    // Sizes from the patch list, plus the real size of files listed without one.
+    pub run_total: u64,
+    pub run_eta_secs: Option<u64>,
+}
+
+// Follows the downloads of a patch run one file at a time. Bytes a resumed
+// download already had on disk count as downloaded but not toward the speed.
+#[derive(Debug)]
+pub struct ProgressTracker {
+    run_total: u64,
+    completed: u64,
+    current: u64,
+    file_size: Option<u64>,
+    listed_size: bool,
+    first_update: bool,
+    bytes_per_sec: Option<f64>,
+    // (time, bytes downloaded in the run) over the last WINDOW.
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl ProgressTracker {
+    pub fn new(run_total: u64) -> Self {
+        ProgressTracker {
+            run_total,
+            completed: 0,
+            current: 0,
+            file_size: None,
+            listed_size: false,
+            first_update: false,
+            bytes_per_sec: None,
+            samples: VecDeque::new(),
+        }
+    }
+    
+    // `size` is the patch list's size for the file, if it has one.
+    pub fn start_file(&mut self, size: Option<u64>) {
+        self.current = 0;
+        self.file_size = size;
+        self.listed_size = size.is_some();
+        self.first_update = true;
+    }
+    
+    pub fn update(&mut self, downloaded: u64, total: u64) -> TransferStats {
+        if !self.listed_size && total > 0 {
+            self.run_total += total.saturating_sub(self.file_size.unwrap_or(0));
+            self.file_size = Some(total);
+        }
+        self.current = downloaded;
+        
+        let run_downloaded = self.run_downloaded();
+        let now = Instant::now();
+        
+        // The first report of a file may include what a resumed download
+        // already had, so it's left out of the speed.
+        if std::mem::take(&mut self.first_update) {
+            for (_, bytes) in self.samples.iter_mut() {
+                *bytes += downloaded;
+            }
+        }
+        
+        if self.samples.back().is_none_or(|(at, _)| now.duration_since(*at) >= SAMPLE_STEP) {
+            self.samples.push_back((now, run_downloaded));
+        }
+        while self.samples.len() > 1 && now.duration_since(self.samples[1].0) >= WINDOW {
+            self.samples.pop_front();
+        }
+        
+        if let Some((at, bytes)) = self.samples.front() {
+            let span = now.duration_since(*at);
+            if span >= MIN_SPAN {
+                self.bytes_per_sec = Some(run_downloaded.saturating_sub(*bytes) as f64 / span.as_secs_f64());
+            }
+        }
+        
+        let file_remaining = self.file_size.unwrap_or(total).saturating_sub(downloaded);
+        TransferStats {
+            bytes_per_sec: self.bytes_per_sec.unwrap_or(0.0) as u64,
+            eta_secs: self.eta(file_remaining),
+            run_downloaded,
+            run_total: self.run_total.max(run_downloaded),
+            run_eta_secs: self.eta(self.run_total.saturating_sub(run_downloaded)),
+        }
+    }
+    
+    // A failed download leaves the run, so its bytes no longer count as pending.
+    pub fn finish_file(&mut self, succeeded: bool) {
+        let size = self.file_size.unwrap_or(self.current);
+        if succeeded {
+            self.completed += size.max(self.current);
+        } else {
+            self.run_total = self.run_total.saturating_sub(size);
+        }
+        self.current = 0;
+        self.file_size = None;
+        self.listed_size = false;
+    }
+    
+    fn run_downloaded(&self) -> u64 {
+        self.completed + self.current
+    }
+    
+    fn eta(&self, remaining: u64) -> Option<u64> {
+        self.bytes_per_sec
+            .filter(|rate| *rate >= 1.0)
+            .map(|rate| (remaining as f64 / rate).ceil() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn resumed_bytes_count_as_downloaded_but_not_toward_speed() {
+        let mut tracker = ProgressTracker::new(3000);
+        
+        tracker.start_file(Some(1000));
+        tracker.update(0, 1000);
+        std::thread::sleep(Duration::from_millis(600));
+        let first = tracker.update(1000, 1000);
+        assert!(first.bytes_per_sec > 0);
+        tracker.finish_file(true);
+        
+        // 1500 bytes were already on disk from an earlier attempt.
+        tracker.start_file(Some(2000));
+        let resumed = tracker.update(1500, 2000);
+        
+        assert_eq!(resumed.run_downloaded, 2500);
+        assert_eq!(resumed.run_total, 3000);
+        assert!(resumed.bytes_per_sec <= first.bytes_per_sec);
+        assert!(resumed.eta_secs.is_some());
+    }
+    
+    #[test]
+    fn failed_file_leaves_the_run_total() {
+        let mut tracker = ProgressTracker::new(3000);
+        
+        tracker.start_file(Some(1000));
+        tracker.update(400, 1000);
+        tracker.finish_file(false);
+        
+        tracker.start_file(Some(2000));
+        let stats = tracker.update(0, 2000);
+        assert_eq!(stats.run_downloaded, 0);
+        assert_eq!(stats.run_total, 2000);
+        
+        tracker.update(2000, 2000);
+        tracker.finish_file(true);
+        tracker.start_file(None);
+        assert_eq!(tracker.update(0, 0).run_downloaded, 2000);
+    }
+    
+    #[test]
+    fn unlisted_size_is_added_once_known() {
+        let mut tracker = ProgressTracker::new(1000);
+        
+        tracker.start_file(None);
+        assert_eq!(tracker.update(0, 0).run_total, 1000);
+        assert_eq!(tracker.update(10, 500).run_total, 1500);
+        assert_eq!(tracker.update(20, 500).run_total, 1500);
+        
+        tracker.finish_file(true);
+        tracker.start_file(Some(1000));
+        let stats = tracker.update(0, 1000);
+        assert_eq!(stats.run_downloaded, 500);
+        assert_eq!(stats.run_total, 1500);
+    }
+}
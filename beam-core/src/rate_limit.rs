@@ -0,0 +1,101 @@
+use crate::config::BandwidthConfig;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Longest single sleep, so limit changes made while a download is throttled
+// take effect quickly.
+const MAX_WAIT: Duration = Duration::from_millis(250);
+// How much unused bandwidth may accumulate, in seconds of the current rate.
+const BURST_SECS: f64 = 0.5;
+
+#[derive(Debug)]
+struct LimiterState {
+    limits: BandwidthConfig,
+    game_running: bool,
+    // Bytes that may be received right now; negative while paying off a burst.
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl LimiterState {
+    fn rate(&self) -> Option<f64> {
+        self.limits
+            .effective_kb_per_sec(self.game_running)
+            .map(|kb| (kb * 1024) as f64)
+    }
+    
+    fn refill(&mut self, rate: f64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate * BURST_SECS);
+        self.refilled_at = now;
+    }
+}
+
+// Token bucket shared by every download stream, so the limit applies to the
+// patcher's combined bandwidth rather than to each connection. Clones share
+// the same bucket and limits can be changed while downloads are running.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    state: Arc<Mutex<LimiterState>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(BandwidthConfig::default())
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limits: BandwidthConfig) -> Self {
+        RateLimiter {
+            state: Arc::new(Mutex::new(LimiterState {
+                limits,
+                game_running: false,
+                tokens: 0.0,
+                refilled_at: Instant::now(),
+            })),
+        }
+    }
+    
+    pub fn limits(&self) -> BandwidthConfig {
+        self.state.lock().unwrap().limits.clone()
+    }
+    
+    pub fn set_limits(&self, limits: BandwidthConfig) {
+        self.state.lock().unwrap().limits = limits;
+    }
+    
+    // Switches between the normal and the in-game limit.
+    pub fn set_game_running(&self, running: bool) {
+        self.state.lock().unwrap().game_running = running;
+    }
+    
+    pub fn game_running(&self) -> bool {
+        self.state.lock().unwrap().game_running
+    }
+    
+    // Waits until `bytes` more may be received under the current limit. A
+    // caller may overdraw the bucket once; later callers wait for the debt.
+    pub async fn acquire(&self, bytes: usize) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let Some(rate) = state.rate() else {
+                    state.tokens = 0.0;
+                    state.refilled_at = Instant::now();
+                    return;
+                };
+                
+                state.refill(rate);
+                if state.tokens >= 0.0 {
+                    state.tokens -= bytes as f64;
+                    return;
+                }
+                Duration::from_secs_f64(-state.tokens / rate)
+            };
+            
+            tokio::time::sleep(wait.min(MAX_WAIT)).await;
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use crate::config::RetryConfig;
+use crate::{Error, Result};
+use bytes::Bytes;
+use reqwest::Client;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::time::Duration;
+use tracing::warn;
+
+impl RetryConfig {
+    // Delay before retry number `retry` (0-based): exponential, capped, with
+    // the jitter fraction of it randomized.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let base = self.initial_backoff_ms
+            .saturating_mul(1u64 << retry.min(20))
+            .min(self.max_backoff_ms);
+        
+        let jitter = self.jitter.clamp(0.0, 1.0) * random_unit();
+        Duration::from_millis((base as f64 * (1.0 - jitter)) as u64)
+    }
+    
+    // How long to wait before trying again after `error`, or None when the
+    // error isn't transient or the retries are used up.
+    pub fn delay_for(&self, error: &Error, retry: u32) -> Option<Duration> {
+        (retry < self.max_retries && error.is_retryable()).then(|| self.backoff(retry))
+    }
+}
+
+// Runs `operation` until it succeeds, fails with a non-retryable error, or
+// the policy's retries are used up.
+pub async fn with_retry<T, F, Fut>(policy: &RetryConfig, what: &str, mut operation: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut retry = 0;
+    
+    loop {
+        let error = match operation().await {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        
+        let Some(delay) = policy.delay_for(&error, retry) else {
+            return Err(error);
+        };
+        
+        retry += 1;
+        warn!("{} failed ({}), retry {}/{} in {} ms", what, error, retry, policy.max_retries, delay.as_millis());
+        tokio::time::sleep(delay).await;
+    }
+}
+
+// GETs a small document, such as a patch list or manifest, with retries.
+pub async fn get_bytes(client: &Client, url: &str, policy: &RetryConfig) -> Result<Bytes> {
+    with_retry(policy, url, || async {
+        let response = client.get(url).send().await?;
+        
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::Http { url: url.to_string(), status: status.as_u16() });
+        }
+        
+        Ok(response.bytes().await?)
+    })
+    .await
+}
+
+// Uniform value in [0, 1); RandomState is seeded randomly per instance,
+// which is plenty for spreading out retries.
+fn random_unit() -> f64 {
+    let random = RandomState::new().hash_one(0u8);
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
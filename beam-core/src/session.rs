@@ -0,0 +1,25 @@
+use crate::control::PatchControl;
+use crate::mirror_health::MirrorHealth;
+use crate::rate_limit::RateLimiter;
+use crate::Config;
+
+// Download state that outlives a single Patcher or Downloader. The UI keeps
+// one for the whole run of the app so mirror health and bandwidth limits
+// carry over between patch runs, and can pause or cancel the running one;
+// clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct DownloadSession {
+    pub mirror_health: MirrorHealth,
+    pub rate_limiter: RateLimiter,
+    pub control: PatchControl,
+}
+
+impl DownloadSession {
+    pub fn new(config: &Config) -> Self {
+        DownloadSession {
+            mirror_health: MirrorHealth::new(),
+            rate_limiter: RateLimiter::new(config.patcher.bandwidth.clone()),
+            control: PatchControl::new(),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::config::RetryConfig;
+use crate::{Config, Error, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use tracing::info;
+
+// Patch lists and manifests are signed with a detached Ed25519 signature,
+// published base64-encoded next to the signed file as `<url>.sig`.
+pub fn signature_url(url: &str) -> String {
+    format!("{}.sig", url)
+}
+
+pub struct SignatureVerifier {
+    key: VerifyingKey,
+}
+
+impl SignatureVerifier {
+    pub fn new(public_key: &str) -> Result<Self> {
+        let bytes: [u8; 32] = decode_fixed(public_key)
+            .map_err(|e| Error::InvalidConfig(format!("Invalid patch signing public key: {}", e)))?;
+        
+        let key = VerifyingKey::from_bytes(&bytes)
+            .map_err(|e| Error::InvalidConfig(format!("Invalid patch signing public key: {}", e)))?;
+        
+        Ok(SignatureVerifier { key })
+    }
+    
+    // Returns None when no public key is configured and signatures aren't checked.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        match config.patcher.public_key.as_deref().map(str::trim) {
+            Some(key) if !key.is_empty() => Ok(Some(Self::new(key)?)),
+            _ => Ok(None),
+        }
+    }
+    
+    pub fn verify(&self, data: &[u8], signature: &str) -> Result<()> {
+        let bytes: [u8; 64] = decode_fixed(signature)
+            .map_err(|e| Error::Signature(format!("malformed signature: {}", e)))?;
+        
+        self.key
+            .verify_strict(data, &Signature::from_bytes(&bytes))
+            .map_err(|_| Error::Signature("signature does not match".to_string()))
+    }
+    
+    // Downloads `<url>.sig` and checks it against the already downloaded body of `url`.
+    pub async fn fetch_and_verify(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        data: &[u8],
+        retry: &RetryConfig,
+    ) -> Result<()> {
+        let sig_url = signature_url(url);
+        let signature = crate::retry::get_bytes(client, &sig_url, retry)
+            .await
+            .map_err(|e| match e {
+                Error::Http { status, .. } => Error::Signature(format!(
+                    "could not download {}: HTTP {}",
+                    sig_url, status
+                )),
+                other => other,
+            })?;
+        
+        let signature = String::from_utf8_lossy(&signature);
+        self.verify(data, &signature).map_err(|e| match e {
+            Error::Signature(reason) => Error::Signature(format!("{} ({})", reason, url)),
+            other => other,
+        })?;
+        
+        info!("Signature verified for {}", url);
+        Ok(())
+    }
+}
+
+// Signs `data` with a base64-encoded 32-byte Ed25519 secret key and returns
+// the base64 signature to publish as the `.sig` file.
+pub fn sign(secret_key: &str, data: &[u8]) -> Result<String> {
+    let key = signing_key(secret_key)?;
+    Ok(BASE64.encode(key.sign(data).to_bytes()))
+}
+
+// Public key for a secret key, in the form expected by `patcher.public_key`.
+pub fn public_key(secret_key: &str) -> Result<String> {
+    let key = signing_key(secret_key)?;
+    Ok(BASE64.encode(key.verifying_key().to_bytes()))
+}
+
+fn signing_key(secret_key: &str) -> Result<SigningKey> {
+    let bytes: [u8; 32] = decode_fixed(secret_key)
+        .map_err(|e| Error::InvalidConfig(format!("Invalid signing key: {}", e)))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+fn decode_fixed<const N: usize>(encoded: &str) -> std::result::Result<[u8; N], String> {
+    let bytes = BASE64
+        .decode(encoded.trim())
+        .map_err(|e| e.to_string())?;
+    
+    let len = bytes.len();
+    bytes
+        .try_into()
+        .map_err(|_| format!("expected {} bytes, got {}", N, len))
+}
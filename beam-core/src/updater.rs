@@ -35,16 +35,21 @@ impl Updater {
         
         info!("Checking for updates at: {}", updater_config.check_url);
         
-        let response = self.client.get(&updater_config.check_url).send().await?;
-        
-        if !response.status().is_success() {
-            return Err(Error::UpdateFailed(format!(
+        let body = crate::retry::get_bytes(
+            &self.client,
+            &updater_config.check_url,
+            &self.config.patcher.retry,
+        )
+        .await
+        .map_err(|e| match e {
+            Error::Http { status, .. } => Error::UpdateFailed(format!(
                 "Failed to check for updates: HTTP {}",
-                response.status()
-            )));
-        }
+                status
+            )),
+            other => other,
+        })?;
         
-        let version_info: VersionInfo = response.json().await?;
+        let version_info: VersionInfo = serde_json::from_slice(&body)?;
         
         if version_info.version != self.config.app.version {
             info!("Update available: {} -> {}", self.config.app.version, version_info.version);
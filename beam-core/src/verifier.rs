@@ -1,7 +1,12 @@
-use crate::{Config, Error, Result};
+use crate::{Config, Downloader, Error, Result};
+use crate::checksum::digests_match;
+use crate::patch_journal::{JournalEntry, PatchJournal};
+use crate::signing::SignatureVerifier;
+use beam_formats::grf::Grf;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 use tracing::{debug, info, warn};
@@ -13,6 +18,8 @@ pub struct FileManifest {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileEntry {
+    // Relative to the game folder, or `data.grf:data\texture\file.bmp` for
+    // an entry inside a GRF.
     pub path: String,
     pub checksum: String,
     pub size: u64,
@@ -26,8 +33,22 @@ pub struct VerificationResult {
     pub missing: Vec<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairFailure {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairResult {
+    // Files that were corrupted or missing and now match the manifest.
+    pub repaired: Vec<String>,
+    pub failed: Vec<RepairFailure>,
+    // The check that found the files to repair.
+    pub verification: VerificationResult,
+}
+
 pub struct Verifier {
-    #[allow(dead_code)]
     config: Config,
     manifest_url: String,
     base_dir: PathBuf,
@@ -52,6 +73,197 @@ impl Verifier {
         info!("Starting game file verification");
         
         let manifest = self.download_manifest().await?;
+        Ok(self.verify_manifest(&manifest).await)
+    }
+    
+    // Verifies the game files, then fetches every corrupted or missing one
+    // from the mirrors again. Each file is downloaded next to its final path,
+    // checked against the manifest and renamed into place, so a failed repair
+    // leaves the old file as it was. Broken GRF entries are fetched from
+    // `<grf name>/<entry path>` on the mirrors and written back with one save
+    // per GRF.
+    pub async fn repair(&self, downloader: &Downloader) -> Result<RepairResult> {
+        let manifest = self.download_manifest().await?;
+        let verification = self.verify_manifest(&manifest).await;
+        let mut repaired = Vec::new();
+        let mut failed = Vec::new();
+        
+        let broken: HashSet<&str> = verification
+            .corrupted
+            .iter()
+            .chain(&verification.missing)
+            .map(String::as_str)
+            .collect();
+        let mut grf_entries: BTreeMap<&str, Vec<(&FileEntry, &str)>> = BTreeMap::new();
+        
+        for file_entry in manifest.files.iter().filter(|entry| broken.contains(entry.path.as_str())) {
+            if let ManifestPath::GrfEntry { grf, entry } = parse_manifest_path(&file_entry.path) {
+                grf_entries.entry(grf).or_default().push((file_entry, entry));
+                continue;
+            }
+            
+            let outcome = self.repair_file(downloader, file_entry).await;
+            tally(&file_entry.path, outcome, &mut repaired, &mut failed)?;
+        }
+        
+        for (grf, entries) in grf_entries {
+            let outcomes = self.repair_grf_entries(downloader, grf, &entries).await?;
+            for ((file_entry, _), outcome) in entries.iter().zip(outcomes) {
+                tally(&file_entry.path, outcome, &mut repaired, &mut failed)?;
+            }
+        }
+        
+        info!("Repair complete: {} repaired, {} failed", repaired.len(), failed.len());
+        
+        Ok(RepairResult {
+            repaired,
+            failed,
+            verification,
+        })
+    }
+    
+    async fn repair_file(&self, downloader: &Downloader, file_entry: &FileEntry) -> Result<()> {
+        let relative = Path::new(&file_entry.path);
+        if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Err(Error::PatchFailed(format!("Refusing to write outside the game folder: {}", file_entry.path)));
+        }
+        
+        let file_path = self.base_dir.join(relative);
+        if let Some(parent) = file_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        
+        let mut download_name = file_path.as_os_str().to_owned();
+        download_name.push(".repair");
+        let download_path = PathBuf::from(download_name);
+        
+        let downloaded = async {
+            downloader
+                .download_checked(&file_entry.path, &download_path, &file_entry.checksum, Some("sha256"))
+                .await?;
+            tokio::fs::OpenOptions::new()
+                .write(true)
+                .open(&download_path)
+                .await?
+                .sync_all()
+                .await?;
+            tokio::fs::rename(&download_path, &file_path).await?;
+            Ok(())
+        }
+        .await;
+        if let Err(e) = downloaded {
+            // A rejected download stays behind under the temporary name.
+            let _ = tokio::fs::remove_file(&download_path).await;
+            return Err(e);
+        }
+        
+        if !self.verify_file(&file_path, &file_entry.checksum).await? {
+            return Err(Error::PatchFailed(format!("{} still fails verification after repair", file_entry.path)));
+        }
+        Ok(())
+    }
+    
+    // Returns one outcome per entry, in order; only a cancel fails the whole
+    // call. The GRF is rebuilt once with every entry that downloaded, then
+    // read back to check them.
+    async fn repair_grf_entries(
+        &self,
+        downloader: &Downloader,
+        grf_name: &str,
+        entries: &[(&FileEntry, &str)],
+    ) -> Result<Vec<Result<()>>> {
+        let all_failed = |reason: String| {
+            entries.iter().map(|_| Err(Error::PatchFailed(reason.clone()))).collect()
+        };
+        
+        let relative = Path::new(grf_name);
+        if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Ok(all_failed(format!("Refusing to write outside the game folder: {}", grf_name)));
+        }
+        
+        // Repairs share the patch journal, so one can't overwrite an entry a
+        // crashed patch run still needs.
+        let journal = PatchJournal::new(downloader.cache_dir());
+        if journal.load()?.is_some() {
+            return Ok(all_failed("An interrupted patch is waiting to be recovered, start patching first".to_string()));
+        }
+        
+        let grf_path = self.base_dir.join(relative);
+        let opened = if grf_path.exists() {
+            Grf::open(&grf_path)
+        } else {
+            Grf::create_new(&grf_path)
+        };
+        let mut grf = match opened {
+            Ok(grf) => grf,
+            Err(e) => return Ok(all_failed(format!("Cannot open {}: {}", grf_name, e))),
+        };
+        
+        // A folder of its own, so repairs of other GRFs or running at the
+        // same time don't overwrite each other's downloads.
+        let temp_dir = match tempfile::Builder::new().prefix("beam_repair_").tempdir() {
+            Ok(temp_dir) => temp_dir,
+            Err(e) => return Ok(all_failed(format!("Cannot create a download folder: {}", e))),
+        };
+        let mut outcomes = Vec::new();
+        
+        for (index, (file_entry, entry)) in entries.iter().enumerate() {
+            let source = format!("{}/{}", grf_name, entry.replace('\\', "/"));
+            let download_path = temp_dir.path().join(format!("entry_{}", index));
+            
+            let data = match downloader
+                .download_checked(&source, &download_path, &file_entry.checksum, Some("sha256"))
+                .await
+            {
+                Ok(path) => tokio::fs::read(&path).await.map_err(Error::from),
+                Err(e) => Err(e),
+            };
+            let _ = tokio::fs::remove_file(&download_path).await;
+            
+            match data {
+                Ok(data) => {
+                    grf.insert_file(&grf_entry_name(entry), data);
+                    outcomes.push(Ok(()));
+                }
+                Err(Error::Cancelled) => return Err(Error::Cancelled),
+                Err(e) => outcomes.push(Err(e)),
+            }
+        }
+        
+        if !grf.has_pending_changes() {
+            return Ok(outcomes);
+        }
+        
+        info!("Writing {} repaired entries into {:?}", outcomes.iter().filter(|o| o.is_ok()).count(), grf_path);
+        // Journaled like a patch, so a crash during the save is recovered by
+        // the next patch run.
+        journal.begin(&JournalEntry::new(None, Path::new(&format!("{} repair", grf_name))))?;
+        let saved = journal.save_grf(&mut grf);
+        journal.clear()?;
+        
+        let saved = saved.and_then(|()| Ok(Grf::open(&grf_path)?));
+        let saved = match saved {
+            Ok(saved) => saved,
+            Err(e) => {
+                let _ = std::fs::remove_file(grf.staging_path());
+                let reason = format!("Could not save {}: {}", grf_name, e);
+                for outcome in outcomes.iter_mut().filter(|o| o.is_ok()) {
+                    *outcome = Err(Error::PatchFailed(reason.clone()));
+                }
+                return Ok(outcomes);
+            }
+        };
+        
+        for ((file_entry, entry), outcome) in entries.iter().zip(outcomes.iter_mut()) {
+            if outcome.is_ok() && !matches!(verify_grf_entry(&saved, &grf_entry_name(entry), &file_entry.checksum), Ok(true)) {
+                *outcome = Err(Error::PatchFailed(format!("{} still fails verification after repair", file_entry.path)));
+            }
+        }
+        
+        Ok(outcomes)
+    }
+    
+    async fn verify_manifest(&self, manifest: &FileManifest) -> VerificationResult {
         let mut result = VerificationResult {
             total_files: manifest.files.len(),
             verified: 0,
@@ -59,16 +271,45 @@ impl Verifier {
             missing: Vec::new(),
         };
         
+        // Each GRF is opened once; an unreadable one is kept as its error.
+        let mut grfs: HashMap<&str, std::result::Result<Option<Grf>, String>> = HashMap::new();
+        
         for file_entry in &manifest.files {
-            let file_path = self.base_dir.join(&file_entry.path);
-            
-            if !file_path.exists() {
-                warn!("Missing file: {}", file_entry.path);
-                result.missing.push(file_entry.path.clone());
-                continue;
-            }
+            let verified = match parse_manifest_path(&file_entry.path) {
+                ManifestPath::Loose(path) => {
+                    let file_path = self.base_dir.join(path);
+                    if !file_path.exists() {
+                        warn!("Missing file: {}", file_entry.path);
+                        result.missing.push(file_entry.path.clone());
+                        continue;
+                    }
+                    self.verify_file(&file_path, &file_entry.checksum).await
+                }
+                ManifestPath::GrfEntry { grf, entry } => {
+                    let archive = grfs.entry(grf).or_insert_with(|| {
+                        let grf_path = self.base_dir.join(grf);
+                        if !grf_path.exists() {
+                            return Ok(None);
+                        }
+                        Grf::open(&grf_path).map(Some).map_err(|e| e.to_string())
+                    });
+                    let name = grf_entry_name(entry);
+                    
+                    match archive {
+                        Ok(Some(archive)) if archive.get_entry(&name).is_some() => {
+                            verify_grf_entry(archive, &name, &file_entry.checksum)
+                        }
+                        Ok(_) => {
+                            warn!("Missing file: {}", file_entry.path);
+                            result.missing.push(file_entry.path.clone());
+                            continue;
+                        }
+                        Err(e) => Err(Error::PatchFailed(format!("Cannot open {}: {}", grf, e))),
+                    }
+                }
+            };
             
-            match self.verify_file(&file_path, &file_entry.checksum).await {
+            match verified {
                 Ok(true) => {
                     debug!("File verified: {}", file_entry.path);
                     result.verified += 1;
@@ -92,23 +333,21 @@ impl Verifier {
             result.missing.len()
         );
         
-        Ok(result)
+        result
     }
     
     async fn download_manifest(&self) -> Result<FileManifest> {
         info!("Downloading file manifest from: {}", self.manifest_url);
         
         let client = reqwest::Client::new();
-        let response = client.get(&self.manifest_url).send().await?;
+        let retry = &self.config.patcher.retry;
+        let body = crate::retry::get_bytes(&client, &self.manifest_url, retry).await?;
         
-        if !response.status().is_success() {
-            return Err(Error::DownloadFailed(format!(
-                "Failed to download manifest: HTTP {}",
-                response.status()
-            )));
+        if let Some(verifier) = SignatureVerifier::from_config(&self.config)? {
+            verifier.fetch_and_verify(&client, &self.manifest_url, &body, retry).await?;
         }
         
-        let manifest: FileManifest = response.json().await?;
+        let manifest: FileManifest = serde_json::from_slice(&body)?;
         info!("Manifest downloaded: {} files", manifest.files.len());
         
         Ok(manifest)
@@ -130,7 +369,7 @@ impl Verifier {
         let result = hasher.finalize();
         let hash = format!("{:x}", result);
         
-        Ok(hash == expected_checksum)
+        Ok(digests_match(&hash, expected_checksum))
     }
     
     pub async fn compute_file_checksum(file_path: &Path) -> Result<String> {
@@ -150,3 +389,70 @@ impl Verifier {
         Ok(format!("{:x}", result))
     }
 }
+
+// Where a manifest entry lives: a file under the game folder, or an entry
+// inside one of its GRFs.
+enum ManifestPath<'a> {
+    Loose(&'a str),
+    GrfEntry { grf: &'a str, entry: &'a str },
+}
+
+fn parse_manifest_path(path: &str) -> ManifestPath<'_> {
+    match path.split_once(':') {
+        Some((grf, entry)) if grf.to_ascii_lowercase().ends_with(".grf") && !entry.is_empty() => {
+            ManifestPath::GrfEntry { grf, entry }
+        }
+        _ => ManifestPath::Loose(path),
+    }
+}
+
+// GRF tables use backslashes; manifests may use either.
+fn grf_entry_name(entry: &str) -> String {
+    entry.replace('/', "\\")
+}
+
+fn verify_grf_entry(grf: &Grf, name: &str, expected_checksum: &str) -> Result<bool> {
+    let data = grf.extract_file(name)?;
+    Ok(digests_match(&format!("{:x}", Sha256::digest(&data)), expected_checksum))
+}
+
+// Sorts a repair outcome into the result; a cancel stops the whole repair.
+fn tally(
+    path: &str,
+    outcome: Result<()>,
+    repaired: &mut Vec<String>,
+    failed: &mut Vec<RepairFailure>,
+) -> Result<()> {
+    match outcome {
+        Ok(()) => {
+            info!("Repaired: {}", path);
+            repaired.push(path.to_string());
+        }
+        Err(Error::Cancelled) => return Err(Error::Cancelled),
+        Err(e) => {
+            warn!("Failed to repair {}: {}", path, e);
+            failed.push(RepairFailure { path: path.to_string(), error: e.to_string() });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn grf_entry_checksum_ignores_case() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut grf = Grf::create_new(dir.path().join("data.grf")).unwrap();
+        grf.insert_file("data\\test.txt", b"hello".to_vec());
+        grf.save().unwrap();
+        
+        let grf = Grf::open(dir.path().join("data.grf")).unwrap();
+        let digest = format!("{:x}", Sha256::digest(b"hello"));
+        
+        assert!(verify_grf_entry(&grf, "data\\test.txt", &digest).unwrap());
+        assert!(verify_grf_entry(&grf, "data\\test.txt", &digest.to_uppercase()).unwrap());
+        assert!(!verify_grf_entry(&grf, "data\\test.txt", &"0".repeat(64)).unwrap());
+    }
+}
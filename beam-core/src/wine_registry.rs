@@ -0,0 +1,360 @@
+use crate::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const REGISTRY_HEADER: &str = "WINE REGISTRY Version 2";
+const FILETIME_UNIX_OFFSET_SECS: u64 = 11_644_473_600;
+
+// Minimal reader/writer for Wine's text registry hives (user.reg, system.reg).
+// The file is kept as raw lines so anything we don't edit is written back
+// byte-for-byte, including comments, hex values and continuation lines.
+//
+// While a wineserver runs for the prefix it holds the registry in memory and
+// writes it back when it exits, replacing edits made here in the meantime.
+// Check `wineserver_running` before editing.
+pub struct WineRegistry {
+    path: PathBuf,
+    lines: Vec<String>,
+}
+
+impl WineRegistry {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        
+        if !content.starts_with(REGISTRY_HEADER) {
+            return Err(Error::InvalidConfig(format!(
+                "Not a Wine registry file: {:?}",
+                path
+            )));
+        }
+        
+        Ok(WineRegistry {
+            path: path.to_path_buf(),
+            lines: content.lines().map(|s| s.to_string()).collect(),
+        })
+    }
+    
+    pub fn user_reg_path<P: AsRef<Path>>(prefix: P) -> PathBuf {
+        prefix.as_ref().join("user.reg")
+    }
+    
+    // Wine puts a server socket in /tmp/.wine-<uid>/server-<dev>-<inode>/ for
+    // each prefix in use, keyed by the prefix directory's device and inode.
+    #[cfg(unix)]
+    pub fn wineserver_running<P: AsRef<Path>>(prefix: P) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        
+        let Ok(meta) = std::fs::metadata(prefix.as_ref()) else {
+            return false;
+        };
+        
+        Path::new("/tmp")
+            .join(format!(".wine-{}", meta.uid()))
+            .join(format!("server-{:x}-{:x}", meta.dev(), meta.ino()))
+            .join("socket")
+            .exists()
+    }
+    
+    #[cfg(not(unix))]
+    pub fn wineserver_running<P: AsRef<Path>>(_prefix: P) -> bool {
+        false
+    }
+    
+    pub fn get_dword(&self, key: &str, name: &str) -> Option<u32> {
+        let raw = self.get_raw_value(key, name)?;
+        let hex = raw.strip_prefix("dword:")?;
+        u32::from_str_radix(hex.trim(), 16).ok()
+    }
+    
+    pub fn get_string(&self, key: &str, name: &str) -> Option<String> {
+        let raw = self.get_raw_value(key, name)?;
+        let quoted = raw.strip_prefix('"')?;
+        let (value, _) = parse_quoted(quoted)?;
+        Some(value)
+    }
+    
+    pub fn set_dword(&mut self, key: &str, name: &str, value: u32) {
+        self.set_raw_value(key, name, &format!("dword:{:08x}", value));
+    }
+    
+    pub fn set_string(&mut self, key: &str, name: &str, value: &str) {
+        self.set_raw_value(key, name, &format!("\"{}\"", escape(value)));
+    }
+    
+    pub fn save(&self) -> Result<()> {
+        let mut content = self.lines.join("\n");
+        content.push('\n');
+        
+        let tmp_path = self.path.with_extension("reg.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+    
+    fn section_range(&self, key: &str) -> Option<(usize, usize)> {
+        let start = self.lines.iter().position(|line| {
+            parse_section_header(line).is_some_and(|k| k.eq_ignore_ascii_case(key))
+        })?;
+        
+        let end = self.lines[start + 1..]
+            .iter()
+            .position(|line| line.starts_with('['))
+            .map(|offset| start + 1 + offset)
+            .unwrap_or(self.lines.len());
+        
+        Some((start, end))
+    }
+    
+    fn find_value_line(&self, key: &str, name: &str) -> Option<usize> {
+        let (start, end) = self.section_range(key)?;
+        
+        (start + 1..end).find(|&idx| {
+            parse_value_name(&self.lines[idx])
+                .is_some_and(|(n, _)| n.eq_ignore_ascii_case(name))
+        })
+    }
+    
+    fn get_raw_value(&self, key: &str, name: &str) -> Option<&str> {
+        let idx = self.find_value_line(key, name)?;
+        parse_value_name(&self.lines[idx]).map(|(_, raw)| raw)
+    }
+    
+    fn set_raw_value(&mut self, key: &str, name: &str, raw: &str) {
+        let line = format!("\"{}\"={}", escape(name), raw);
+        
+        if let Some(idx) = self.find_value_line(key, name) {
+            // Replace continuation lines of the old value too (hex data can span lines).
+            let mut last = idx;
+            while self.lines[last].ends_with('\\') && last + 1 < self.lines.len() {
+                last += 1;
+            }
+            self.lines.splice(idx..=last, [line]);
+            return;
+        }
+        
+        match self.section_range(key) {
+            Some((start, end)) => {
+                let insert_at = (start + 1..end)
+                    .rev()
+                    .find(|&idx| !self.lines[idx].trim().is_empty())
+                    .map(|idx| idx + 1)
+                    .unwrap_or(start + 1);
+                self.lines.insert(insert_at, line);
+            }
+            None => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                let filetime = (now + FILETIME_UNIX_OFFSET_SECS) * 10_000_000;
+                
+                if self.lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                    self.lines.push(String::new());
+                }
+                self.lines.push(format!("[{}] {}", escape(key), now));
+                self.lines.push(format!("#time={:x}", filetime));
+                self.lines.push(line);
+            }
+        }
+    }
+}
+
+fn parse_section_header(line: &str) -> Option<String> {
+    let rest = line.strip_prefix('[')?;
+    let end = rest.rfind(']')?;
+    Some(unescape(&rest[..end]))
+}
+
+fn parse_value_name(line: &str) -> Option<(String, &str)> {
+    if let Some(rest) = line.strip_prefix("@=") {
+        return Some(("@".to_string(), rest));
+    }
+    
+    let quoted = line.strip_prefix('"')?;
+    let (name, consumed) = parse_quoted(quoted)?;
+    let rest = quoted[consumed..].strip_prefix('=')?;
+    Some((name, rest))
+}
+
+// Parses the body of a quoted string (opening quote already stripped) and
+// returns the unescaped value and the number of bytes consumed, including the
+// closing quote.
+fn parse_quoted(input: &str) -> Option<(String, usize)> {
+    let mut value = String::new();
+    let mut chars = input.char_indices();
+    
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                '0' => value.push('\0'),
+                other => value.push(other),
+            },
+            '"' => return Some((value, idx + 1)),
+            _ => value.push(c),
+        }
+    }
+    
+    None
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(next) = chars.next() {
+                unescaped.push(next);
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const RAGNAROK: &str = r"Software\Gravity Soft\Ragnarok";
+    
+    const USER_REG: &str = r#"WINE REGISTRY Version 2
+;; All keys relative to \\User\\S-1-5-21-0-0-0-1000
+
+#arch=win64
+
+[Software\\Gravity Soft\\Ragnarok] 1700000000
+#time=1d9a0b0c0d0e0f0
+"Blob"=hex:01,02,03,04,05,06,07,08,09,0a,0b,0c,0d,0e,0f,10,11,12,13,14,15,16,\
+  17,18,19,1a
+"Name"="C:\\Games\\RO \"test\""
+"WIDTH"=dword:00000400
+
+[Software\\Wine\\Direct3D] 1700000001
+#time=1d9a0b0c0d0e0f1
+"renderer"="vulkan"
+"#;
+    
+    fn open(content: &str) -> (tempfile::TempDir, WineRegistry) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = WineRegistry::user_reg_path(dir.path());
+        std::fs::write(&path, content).unwrap();
+        let registry = WineRegistry::open(&path).unwrap();
+        (dir, registry)
+    }
+    
+    fn saved(dir: &tempfile::TempDir) -> String {
+        std::fs::read_to_string(WineRegistry::user_reg_path(dir.path())).unwrap()
+    }
+    
+    #[test]
+    fn unchanged_file_round_trips() {
+        let (dir, registry) = open(USER_REG);
+        registry.save().unwrap();
+        assert_eq!(saved(&dir), USER_REG);
+    }
+    
+    #[test]
+    fn rejects_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("user.reg");
+        std::fs::write(&path, "REGEDIT4\n").unwrap();
+        assert!(WineRegistry::open(&path).is_err());
+    }
+    
+    #[test]
+    fn reads_values_under_escaped_key() {
+        let (_dir, registry) = open(USER_REG);
+        
+        assert_eq!(registry.get_dword(RAGNAROK, "WIDTH"), Some(0x400));
+        assert_eq!(registry.get_dword(&RAGNAROK.to_uppercase(), "width"), Some(0x400));
+        assert_eq!(registry.get_string(RAGNAROK, "Name").as_deref(), Some(r#"C:\Games\RO "test""#));
+        assert_eq!(registry.get_string(r"Software\Wine\Direct3D", "renderer").as_deref(), Some("vulkan"));
+        assert_eq!(registry.get_dword(RAGNAROK, "Blob"), None);
+        assert_eq!(registry.get_dword(r"Software\Gravity Soft", "WIDTH"), None);
+    }
+    
+    #[test]
+    fn replacing_a_value_leaves_other_lines_alone() {
+        let (dir, mut registry) = open(USER_REG);
+        registry.set_dword(RAGNAROK, "WIDTH", 1920);
+        registry.save().unwrap();
+        
+        assert_eq!(saved(&dir), USER_REG.replace("dword:00000400", "dword:00000780"));
+    }
+    
+    #[test]
+    fn replacing_hex_value_drops_its_continuation_lines() {
+        let (dir, mut registry) = open(USER_REG);
+        registry.set_dword(RAGNAROK, "Blob", 1);
+        registry.save().unwrap();
+        
+        let expected = USER_REG.replace(
+            "\"Blob\"=hex:01,02,03,04,05,06,07,08,09,0a,0b,0c,0d,0e,0f,10,11,12,13,14,15,16,\\\n  17,18,19,1a\n",
+            "\"Blob\"=dword:00000001\n",
+        );
+        assert_ne!(expected, USER_REG);
+        assert_eq!(saved(&dir), expected);
+        
+        let reopened = WineRegistry::open(WineRegistry::user_reg_path(dir.path())).unwrap();
+        assert_eq!(reopened.get_dword(RAGNAROK, "WIDTH"), Some(0x400));
+    }
+    
+    #[test]
+    fn new_value_goes_at_the_end_of_its_section() {
+        let (dir, mut registry) = open(USER_REG);
+        registry.set_dword(RAGNAROK, "HEIGHT", 768);
+        registry.save().unwrap();
+        
+        let expected = USER_REG.replace(
+            "\"WIDTH\"=dword:00000400\n",
+            "\"WIDTH\"=dword:00000400\n\"HEIGHT\"=dword:00000300\n",
+        );
+        assert_eq!(saved(&dir), expected);
+    }
+    
+    #[test]
+    fn missing_section_is_appended_with_escaped_header() {
+        let (dir, mut registry) = open(USER_REG);
+        registry.set_string(r"Software\Beam\Patcher", "Path", r"C:\Beam");
+        registry.save().unwrap();
+        
+        let content = saved(&dir);
+        assert!(content.starts_with(USER_REG));
+        
+        let added: Vec<&str> = content[USER_REG.len()..].lines().collect();
+        assert_eq!(added.len(), 4);
+        assert_eq!(added[0], "");
+        assert!(added[1].starts_with(r"[Software\\Beam\\Patcher] "));
+        assert!(added[2].starts_with("#time="));
+        assert_eq!(added[3], r#""Path"="C:\\Beam""#);
+        
+        let reopened = WineRegistry::open(WineRegistry::user_reg_path(dir.path())).unwrap();
+        assert_eq!(reopened.get_string(r"Software\Beam\Patcher", "Path").as_deref(), Some(r"C:\Beam"));
+    }
+    
+    #[test]
+    fn no_wineserver_for_unused_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!WineRegistry::wineserver_running(dir.path()));
+    }
+}
@@ -2,7 +2,7 @@ use crate::{Error, Result};
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
@@ -30,6 +30,8 @@ pub struct Grf {
     entries: HashMap<String, GrfEntry>,
     file_path: PathBuf,
     pending_patches: HashMap<String, Vec<u8>>,
+    pending_removals: HashSet<String>,
+    staged_entries: Option<HashMap<String, GrfEntry>>,
 }
 
 impl Grf {
@@ -51,6 +53,8 @@ impl Grf {
             entries: HashMap::new(),
             file_path: path.to_path_buf(),
             pending_patches: HashMap::new(),
+            pending_removals: HashSet::new(),
+            staged_entries: None,
         })
     }
     
@@ -117,6 +121,8 @@ impl Grf {
             entries,
             file_path: path.to_path_buf(),
             pending_patches: HashMap::new(),
+            pending_removals: HashSet::new(),
+            staged_entries: None,
         })
     }
     
@@ -387,16 +393,44 @@ impl Grf {
                 .map_err(|e| Error::Decompression(e.to_string()))?;
             Ok(decompressed)
         } else {
+            // Stored entries are padded to the 8-byte alignment.
+            compressed_data.truncate(entry.uncompressed_size as usize);
             Ok(compressed_data)
         }
     }
     
     pub fn patch_file(&mut self, filename: &str, data: &[u8]) -> Result<()> {
+        self.insert_file(filename, data.to_vec());
+        Ok(())
+    }
+    
+    // Like patch_file, but keeps the given buffer instead of copying it.
+    pub fn insert_file(&mut self, filename: &str, data: Vec<u8>) {
         // Store uncompressed data in memory for later rebuild
-        tracing::info!("patch_file() called for: {} ({} bytes)", filename, data.len());
-        self.pending_patches.insert(filename.to_string(), data.to_vec());
+        tracing::info!("insert_file() called for: {} ({} bytes)", filename, data.len());
+        self.pending_removals.remove(filename);
+        self.pending_patches.insert(filename.to_string(), data);
         tracing::debug!("Total pending patches now: {}", self.pending_patches.len());
-        Ok(())
+    }
+    
+    // Drops a file on the next save, including one patched since the last save.
+    pub fn remove_file(&mut self, filename: &str) {
+        tracing::info!("remove_file() called for: {}", filename);
+        self.pending_patches.remove(filename);
+        if self.entries.contains_key(filename) {
+            self.pending_removals.insert(filename.to_string());
+        }
+    }
+    
+    // Whether the file will be in the archive after the next save.
+    pub fn contains_file(&self, filename: &str) -> bool {
+        self.pending_patches.contains_key(filename)
+            || (self.entries.contains_key(filename) && !self.pending_removals.contains(filename))
+    }
+    
+    // Uncompressed size of the patched files held in memory until the next save.
+    pub fn pending_bytes(&self) -> u64 {
+        self.pending_patches.values().map(|data| data.len() as u64).sum()
     }
     
     pub fn list_files(&self) -> Vec<&str> {
@@ -407,27 +441,62 @@ impl Grf {
         self.entries.len()
     }
     
+    pub fn path(&self) -> &Path {
+        &self.file_path
+    }
+    
+    pub fn has_pending_changes(&self) -> bool {
+        !self.pending_patches.is_empty() || !self.pending_removals.is_empty()
+    }
+    
+    // Path the rebuilt archive is written to before it replaces the original.
+    pub fn staging_path(&self) -> PathBuf {
+        self.file_path.with_extension("grf.new")
+    }
+    
     pub fn save(&mut self) -> Result<()> {
+        if let Some(staged_path) = self.stage()? {
+            self.commit(&staged_path)?;
+        }
+        Ok(())
+    }
+    
+    // Atomically replaces the archive with the one written by stage().
+    pub fn commit(&mut self, staged_path: &Path) -> Result<()> {
+        tracing::info!("Replacing {:?} with staged GRF {:?}", self.file_path, staged_path);
+        std::fs::rename(staged_path, &self.file_path)?;
+        sync_parent_dir(&self.file_path)?;
+        
+        if let Some(entries) = self.staged_entries.take() {
+            self.entries = entries;
+        }
+        self.pending_patches.clear();
+        self.pending_removals.clear();
+        
+        tracing::info!("GRF save completed successfully - total entries: {}", self.entries.len());
+        Ok(())
+    }
+    
+    // Rebuilds the archive with all pending patches into staging_path() and
+    // syncs it to disk. The original file is only read, so a crash or a full
+    // disk at any point here leaves it intact. Returns None if nothing changed.
+    pub fn stage(&mut self) -> Result<Option<PathBuf>> {
         if self.version != GRF_VERSION_0X200 && self.version != GRF_VERSION_0X300 {
             return Err(Error::Unsupported("Only GRF 0x200 and 0x300 save is supported".to_string()));
         }
         
-        tracing::info!("GRF save() called - pending patches: {}, existing entries: {}", 
-            self.pending_patches.len(), self.entries.len());
+        tracing::info!("GRF stage() called - pending patches: {}, removals: {}, existing entries: {}", 
+            self.pending_patches.len(), self.pending_removals.len(), self.entries.len());
         
-        if self.pending_patches.is_empty() {
+        if !self.has_pending_changes() {
             tracing::info!("No pending patches, skipping save");
-            return Ok(());
+            return Ok(None);
         }
         
-        tracing::info!("Starting GRF rebuild at: {:?}", self.file_path);
+        let staged_path = self.staging_path();
+        tracing::info!("Starting GRF rebuild of {:?} into {:?}", self.file_path, staged_path);
         
-        // Rebuild GRF with pending patches
-        let backup_path = self.file_path.with_extension("grf.bak");
-        tracing::info!("Creating backup: {:?}", backup_path);
-        std::fs::rename(&self.file_path, &backup_path)?;
-        
-        let mut new_file = std::fs::File::create(&self.file_path)?;
+        let mut new_file = std::fs::File::create(&staged_path)?;
         
         // Write header (46 bytes): magic(16) + key(14) + offset(4) + seed(4) + count(4) + version(4)
         new_file.write_all(GRF_HEADER)?;
@@ -441,13 +510,16 @@ impl Grf {
         // Start writing file data at offset 46 (after header)
         let mut current_offset: u32 = 0;
         let mut new_entries = HashMap::new();
-        let mut old_grf = std::fs::File::open(&backup_path)?;
+        let mut old_grf = std::fs::File::open(&self.file_path)?;
         
         // Copy existing files that are not being patched
         for (filename, entry) in &self.entries {
             if self.pending_patches.contains_key(filename) {
                 continue; // Skip, will be replaced by patch
             }
+            if self.pending_removals.contains(filename) {
+                continue; // Removed by a patch
+            }
             
             // Read old file data
             old_grf.seek(SeekFrom::Start((entry.offset + GRF_HEADER_SIZE) as u64))?;
@@ -551,19 +623,27 @@ impl Grf {
         new_file.write_all(&(new_entries.len() as u32).to_le_bytes())?; // FilesCount (offset 38)
         // Version at offset 42 is already written in create_new(), don't overwrite
         
+        new_file.sync_all()?;
         drop(new_file);
         drop(old_grf);
         
-        // Delete backup
-        tracing::info!("Deleting backup file: {:?}", backup_path);
-        std::fs::remove_file(&backup_path)?;
+        self.staged_entries = Some(new_entries);
         
-        // Update internal state
-        self.entries = new_entries;
-        self.pending_patches.clear();
-        
-        tracing::info!("GRF save completed successfully - total entries: {}", self.entries.len());
-        
-        Ok(())
+        Ok(Some(staged_path))
     }
 }
+
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// Directory handles can't be synced on Windows; the rename is already
+// write-through there.
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
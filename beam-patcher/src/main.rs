@@ -1,9 +1,10 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use anyhow::Result;
-use beam_core::Config;
+use beam_core::{Config, PatchEvent, PatchPlan};
 use beam_ui;
 use clap::Parser;
+use tokio::sync::mpsc::UnboundedReceiver;
 use tracing_subscriber;
 
 #[derive(Parser, Debug)]
@@ -17,6 +18,10 @@ struct Args {
     
     #[arg(short, long)]
     manual_patch: Option<String>,
+    
+    /// Show what the pending patches would change, without applying them
+    #[arg(long)]
+    plan: bool,
 }
 
 #[tokio::main]
@@ -46,7 +51,9 @@ async fn main() -> Result<()> {
         config
     };
     
-    if args.headless {
+    if args.plan {
+        run_plan(config).await?;
+    } else if args.headless {
         run_headless(config, args.manual_patch).await?;
     } else {
         beam_ui::run_ui(config)?;
@@ -57,15 +64,140 @@ async fn main() -> Result<()> {
 
 async fn run_headless(config: Config, manual_patch: Option<String>) -> Result<()> {
     let patcher = beam_core::Patcher::new(config)?;
+    let printer = tokio::spawn(print_events(patcher.subscribe()));
+    
+    // Ctrl+C stops at the next safe point instead of killing a GRF save.
+    let control = patcher.control();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            tracing::warn!("Cancelling, waiting for the current step to finish...");
+            control.cancel();
+        }
+    });
     
-    if let Some(patch_path) = manual_patch {
+    let result = if let Some(patch_path) = manual_patch {
         tracing::info!("Applying manual patch: {}", patch_path);
-        patcher.manual_patch(std::path::Path::new(&patch_path)).await?;
+        patcher.manual_patch(std::path::Path::new(&patch_path)).await
     } else {
         tracing::info!("Starting full patch process");
-        patcher.run_full_patch().await?;
-    }
+        patcher.run_full_patch().await
+    };
+    
+    // The event stream ends with the patcher; let the printer catch up.
+    drop(patcher);
+    let _ = printer.await;
+    result?;
     
     tracing::info!("Patching completed successfully");
     Ok(())
 }
+
+async fn run_plan(config: Config) -> Result<()> {
+    let patcher = beam_core::Patcher::new(config)?;
+    let printer = tokio::spawn(print_events(patcher.subscribe()));
+    
+    let control = patcher.control();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            control.cancel();
+        }
+    });
+    
+    let result = patcher.plan().await;
+    drop(patcher);
+    let _ = printer.await;
+    
+    print_plan(&result?);
+    Ok(())
+}
+
+fn print_plan(plan: &PatchPlan) {
+    for patch in &plan.patches {
+        match &patch.skipped {
+            Some(reason) => println!("{} -> {} (skipped: {})", patch.filename, patch.target, reason),
+            None => println!("{} -> {}", patch.filename, patch.target),
+        }
+    }
+    
+    if plan.is_empty() {
+        println!("Nothing would change");
+        return;
+    }
+    
+    for target in plan.targets.iter().filter(|target| !target.is_empty()) {
+        println!(
+            "{}: {} added, {} replaced, {} removed, {:+} bytes",
+            target.target,
+            target.added.len(),
+            target.replaced.len(),
+            target.removed.len(),
+            target.size_delta
+        );
+        for file in &target.added {
+            println!("  + {} ({} bytes)", file.name, file.new_size.unwrap_or(0));
+        }
+        for file in &target.replaced {
+            println!("  ~ {} ({} -> {} bytes)", file.name, file.old_size.unwrap_or(0), file.new_size.unwrap_or(0));
+        }
+        for file in &target.removed {
+            println!("  - {} ({} bytes)", file.name, file.old_size.unwrap_or(0));
+        }
+    }
+}
+
+// Prints the patch run to the console, with download progress in 10% steps.
+async fn print_events(mut events: UnboundedReceiver<PatchEvent>) {
+    let mut last_step = None;
+    
+    while let Some(event) = events.recv().await {
+        match event {
+            PatchEvent::PatchListFetched { count, total_bytes } => {
+                println!("{} patch(es) to apply, {} bytes", count, total_bytes);
+            }
+            PatchEvent::DownloadStarted { index, filename, .. } => {
+                println!("[{}] Downloading {}", index + 1, filename);
+            }
+            PatchEvent::DownloadProgress { index, filename, downloaded, total, stats } if total > 0 => {
+                let step = downloaded * 10 / total;
+                if last_step != Some((index, step)) {
+                    last_step = Some((index, step));
+                    let eta = stats.run_eta_secs
+                        .map(|secs| format!(", {}m{:02}s left", secs / 60, secs % 60))
+                        .unwrap_or_default();
+                    println!("[{}] {}: {}% at {} KB/s{}", index + 1, filename, step * 10, stats.bytes_per_sec / 1024, eta);
+                }
+            }
+            PatchEvent::DownloadFinished { index, filename } => {
+                println!("[{}] Downloaded {}", index + 1, filename);
+            }
+            PatchEvent::Verifying { index, filename } => {
+                println!("[{}] Verifying {}", index + 1, filename);
+            }
+            PatchEvent::Applying { index, filename } => {
+                println!("[{}] Applying {}", index + 1, filename);
+            }
+            PatchEvent::ApplyingEntry { index, entry, entries, name } => {
+                tracing::debug!("[{}] {}/{}: {}", index + 1, entry, entries, name);
+            }
+            PatchEvent::Saved { target, patches } => {
+                println!("Saved {} ({} patch(es))", target, patches);
+            }
+            PatchEvent::Applied { index, filename } => {
+                println!("[{}] Applied {}", index + 1, filename);
+            }
+            PatchEvent::Skipped { index, filename, reason } => {
+                println!("[{}] Skipped {}: {}", index + 1, filename, reason);
+            }
+            PatchEvent::Failed { error } => {
+                eprintln!("Patching failed: {}", error);
+            }
+            PatchEvent::Cancelled => {
+                println!("Patching cancelled");
+            }
+            PatchEvent::Finished { applied, skipped } => {
+                println!("Done: {} applied, {} skipped", applied, skipped);
+            }
+            PatchEvent::DownloadProgress { .. } => {}
+        }
+    }
+}
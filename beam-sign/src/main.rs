@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use beam_core::signing::{self, SignatureVerifier};
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+
+// Release tool: signs patch lists and file manifests for `patcher.public_key`.
+// The key file holds a base64 32-byte secret, e.g. `openssl rand -base64 32`.
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Sign patch lists and manifests for Beam Patcher", long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write a detached <file>.sig signature next to each file
+    Sign {
+        #[arg(short, long)]
+        key: PathBuf,
+        
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    
+    /// Print the public key to put in patcher.public_key
+    PublicKey {
+        #[arg(short, long)]
+        key: PathBuf,
+    },
+    
+    /// Check a file against its <file>.sig signature
+    Verify {
+        #[arg(short, long)]
+        public_key: String,
+        
+        file: PathBuf,
+    },
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    
+    match args.command {
+        Command::Sign { key, files } => {
+            let secret = read_key(&key)?;
+            
+            for file in files {
+                let data = std::fs::read(&file)
+                    .with_context(|| format!("Failed to read {:?}", file))?;
+                let signature = signing::sign(&secret, &data)?;
+                
+                let sig_path = sig_path(&file);
+                std::fs::write(&sig_path, format!("{}\n", signature))?;
+                println!("Signed {:?} -> {:?}", file, sig_path);
+            }
+        }
+        Command::PublicKey { key } => {
+            println!("{}", signing::public_key(&read_key(&key)?)?);
+        }
+        Command::Verify { public_key, file } => {
+            let verifier = SignatureVerifier::new(&public_key)?;
+            let data = std::fs::read(&file)
+                .with_context(|| format!("Failed to read {:?}", file))?;
+            let signature = std::fs::read_to_string(sig_path(&file))?;
+            
+            verifier.verify(&data, &signature)?;
+            println!("Signature OK: {:?}", file);
+        }
+    }
+    
+    Ok(())
+}
+
+fn read_key(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).with_context(|| format!("Failed to read key file {:?}", path))
+}
+
+fn sig_path(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".sig");
+    PathBuf::from(name)
+}
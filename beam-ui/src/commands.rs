@@ -1,8 +1,9 @@
-use beam_core::{Config, Patcher, Verifier, VerificationResult, GameSettings, GameSettingsManager, ServerChecker, ServerStatusResult, ClientChecker, ClientStatusResult};
+use beam_core::{BandwidthConfig, ChangeLogQuery, Config, Downloader, MirrorStatus, PatchChanges, PatchControl, PatchEvent, PatchPlan, Patcher, RepairResult, Verifier, VerificationResult, GameSettings, GameSettingsManager, ClientLuaFile, LuaDocument, LuaKey, LuaValue, GamePreset, GamePresetStore, SettingDifference, ServerChecker, ServerStatusResult, ClientChecker, ClientStatusResult};
 use serde::{Deserialize, Serialize};
 use tauri::{State, AppHandle, Manager};
 use crate::{AppState, PatchProgress};
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NewsItem {
@@ -28,7 +29,8 @@ pub struct NewsApiResponse {
 #[tauri::command]
 pub async fn check_patches_available(state: State<'_, AppState>) -> Result<usize, String> {
     let config = state.config.lock().unwrap().clone();
-    let patcher = Patcher::new(config).map_err(|e| e.to_string())?;
+    let patcher = Patcher::with_session(config, state.session.clone())
+        .map_err(|e| e.to_string())?;
     
     let patch_count = patcher.check_available_patches().await.map_err(|e| e.to_string())?;
     
@@ -37,6 +39,8 @@ pub async fn check_patches_available(state: State<'_, AppState>) -> Result<usize
 
 #[tauri::command]
 pub async fn start_patching(state: State<'_, AppState>, app: AppHandle) -> Result<(), String> {
+    let _game_files = state.game_files.try_lock()
+        .map_err(|_| "Patching or a repair is already running".to_string())?;
     let config = state.config.lock().unwrap().clone();
     
     let progress_state = state.progress.clone();
@@ -48,45 +52,30 @@ pub async fn start_patching(state: State<'_, AppState>, app: AppHandle) -> Resul
         progress.total = 0;
         progress.bytes_downloaded = 0;
         progress.bytes_total = 0;
+        progress.bytes_per_sec = 0;
+        progress.eta_secs = None;
+        progress.overall_downloaded = 0;
+        progress.overall_total = 0;
+        progress.overall_eta_secs = None;
     }
     
-    let patcher = Patcher::new(config).map_err(|e| e.to_string())?;
+    state.session.control.reset();
+    let patcher = Patcher::with_session(config, state.session.clone())
+        .map_err(|e| e.to_string())?;
+    
+    let events = patcher.subscribe();
+    let forwarder = tokio::spawn(forward_patch_events(events, progress_state.clone(), app.clone()));
     
     let patches = patcher.get_patch_list().await.map_err(|e| e.to_string())?;
     let total_patches = patches.len();
     
-    {
-        let mut progress = progress_state.lock().unwrap();
-        progress.total = total_patches;
-        progress.status = format!("Found {} patches to download", total_patches);
-    }
-    
-    app.emit_all("patch-progress", progress_state.lock().unwrap().clone())
-        .map_err(|e: tauri::Error| e.to_string())?;
+    patcher.apply_patches(patches).await.map_err(|e| e.to_string())?;
+    patcher.install_client_info().await.map_err(|e| e.to_string())?;
     
-    for (idx, patch) in patches.iter().enumerate() {
-        {
-            let mut progress = progress_state.lock().unwrap();
-            progress.current = idx + 1;
-            progress.filename = patch.filename.clone();
-            progress.status = format!("Downloading {} ({}/{})", patch.filename, idx + 1, total_patches);
-            progress.bytes_total = patch.size.unwrap_or(0);
-            progress.bytes_downloaded = 0;
-        }
-        
-        app.emit_all("patch-progress", progress_state.lock().unwrap().clone())
-            .map_err(|e: tauri::Error| e.to_string())?;
-        
-        let progress_clone = progress_state.clone();
-        let app_clone = app.clone();
-        
-        patcher.download_and_apply_patch(patch, move |downloaded, total| {
-            let mut progress = progress_clone.lock().unwrap();
-            progress.bytes_downloaded = downloaded;
-            progress.bytes_total = total;
-            let _ = app_clone.emit_all("patch-progress", progress.clone());
-        }).await.map_err(|e| e.to_string())?;
-    }
+    // Dropping the patcher ends the event stream; wait until it's drained so
+    // the final status isn't overwritten by a late event.
+    drop(patcher);
+    let _ = forwarder.await;
     
     {
         let mut progress = progress_state.lock().unwrap();
@@ -100,6 +89,127 @@ pub async fn start_patching(state: State<'_, AppState>, app: AppHandle) -> Resul
     Ok(())
 }
 
+// Folds patch events into the progress summary the launcher shows, and passes
+// each event on as-is for anything that wants the details. Downloads run ahead
+// of patch application, so the byte counters follow the patch being
+// downloaded while the status names the one being applied.
+async fn forward_patch_events(
+    mut events: tokio::sync::mpsc::UnboundedReceiver<PatchEvent>,
+    progress_state: Arc<Mutex<PatchProgress>>,
+    app: AppHandle,
+) {
+    while let Some(event) = events.recv().await {
+        let _ = app.emit_all("patch-event", event.clone());
+        
+        let mut progress = progress_state.lock().unwrap();
+        let total = progress.total;
+        match event {
+            PatchEvent::PatchListFetched { count, total_bytes } => {
+                progress.total = count;
+                progress.overall_total = total_bytes;
+                progress.status = format!("Found {} patches to download", count);
+            }
+            PatchEvent::DownloadStarted { index, filename, .. } => {
+                progress.status = format!("Downloading {} ({}/{})", filename, index + 1, total);
+                progress.filename = filename;
+                progress.bytes_downloaded = 0;
+                progress.bytes_total = 0;
+            }
+            PatchEvent::DownloadProgress { downloaded, total, stats, .. } => {
+                progress.bytes_downloaded = downloaded;
+                progress.bytes_total = total;
+                progress.bytes_per_sec = stats.bytes_per_sec;
+                progress.eta_secs = stats.eta_secs;
+                progress.overall_downloaded = stats.run_downloaded;
+                progress.overall_total = stats.run_total;
+                progress.overall_eta_secs = stats.run_eta_secs;
+            }
+            PatchEvent::Applying { index, filename } => {
+                progress.current = index + 1;
+                progress.status = format!("Applying {} ({}/{})", filename, index + 1, total);
+            }
+            PatchEvent::Applied { index, .. } | PatchEvent::Skipped { index, .. } => {
+                progress.current = progress.current.max(index + 1);
+            }
+            PatchEvent::Failed { error } => {
+                progress.status = format!("Patching failed: {}", error);
+            }
+            PatchEvent::Cancelled => {
+                progress.status = "Patching cancelled".to_string();
+            }
+            // Per-entry and per-save events are too fine-grained for the summary.
+            _ => continue,
+        }
+        let _ = app.emit_all("patch-progress", progress.clone());
+    }
+}
+
+// Downloads the pending patches to the temp folder and reports what they would
+// change, leaving the client untouched. Refused during a patch run, which
+// downloads into the same temp files.
+#[tauri::command]
+pub async fn plan_patches(state: State<'_, AppState>) -> Result<PatchPlan, String> {
+    let _game_files = state.game_files.try_lock()
+        .map_err(|_| "Patching or a repair is already running".to_string())?;
+    let config = state.config.lock().unwrap().clone();
+    // Its own control, so a cancel meant for a patch run doesn't stop it.
+    let mut session = state.session.clone();
+    session.control = PatchControl::new();
+    let patcher = Patcher::with_session(config, session)
+        .map_err(|e| e.to_string())?;
+    
+    patcher.plan().await.map_err(|e| e.to_string())
+}
+
+// Entries changed by applied patches, newest patch first.
+#[tauri::command]
+pub async fn get_change_log(state: State<'_, AppState>, query: ChangeLogQuery) -> Result<Vec<PatchChanges>, String> {
+    let config = state.config.lock().unwrap().clone();
+    let patcher = Patcher::new(config).map_err(|e| e.to_string())?;
+    
+    patcher.change_log().query(&query).map_err(|e| e.to_string())
+}
+
+// Pause takes effect at the next safe point: between download chunks, patch
+// entries or patches. A GRF save in progress always finishes first.
+#[tauri::command]
+pub async fn pause_patching(state: State<'_, AppState>, app: AppHandle) -> Result<(), String> {
+    state.session.control.pause();
+    set_patch_status(&state, &app, "Paused")
+}
+
+#[tauri::command]
+pub async fn resume_patching(state: State<'_, AppState>, app: AppHandle) -> Result<(), String> {
+    state.session.control.resume();
+    set_patch_status(&state, &app, "Resuming...")
+}
+
+#[tauri::command]
+pub async fn cancel_patching(state: State<'_, AppState>, app: AppHandle) -> Result<(), String> {
+    state.session.control.cancel();
+    set_patch_status(&state, &app, "Cancelling...")
+}
+
+fn set_patch_status(state: &AppState, app: &AppHandle, status: &str) -> Result<(), String> {
+    let mut progress = state.progress.lock().unwrap();
+    progress.status = status.to_string();
+    app.emit_all("patch-progress", progress.clone())
+        .map_err(|e: tauri::Error| e.to_string())
+}
+
+// Refused while a patch run or repair is rewriting the same GRF.
+#[tauri::command]
+pub async fn apply_client_info(state: State<'_, AppState>) -> Result<Option<String>, String> {
+    let _game_files = state.game_files.try_lock()
+        .map_err(|_| "Patching or a repair is already running".to_string())?;
+    let config = state.config.lock().unwrap().clone();
+    let patcher = Patcher::new(config).map_err(|e| e.to_string())?;
+    
+    let path = patcher.install_client_info().await.map_err(|e| e.to_string())?;
+    
+    Ok(path.map(|p| p.to_string_lossy().to_string()))
+}
+
 #[tauri::command]
 pub async fn check_updates(state: State<'_, AppState>) -> Result<Option<String>, String> {
     let config = state.config.lock().unwrap().clone();
@@ -192,10 +302,19 @@ pub async fn launch_game(
     #[cfg(target_os = "windows")]
     {
         use std::process::Command;
-        Command::new(&client_exe)
+        let mut child = Command::new(&client_exe)
             .current_dir(&working_dir)
             .spawn()
             .map_err(|e| format!("Failed to launch game: {}", e))?;
+        
+        // Patching while playing falls back to the in-game bandwidth limit
+        // until the client exits.
+        let limiter = state.session.rate_limiter.clone();
+        limiter.set_game_running(true);
+        std::thread::spawn(move || {
+            let _ = child.wait();
+            limiter.set_game_running(false);
+        });
     }
     
     #[cfg(not(target_os = "windows"))]
@@ -260,16 +379,38 @@ pub async fn get_server_status(state: State<'_, AppState>) -> Result<ServerStatu
 pub async fn verify_game_files(state: State<'_, AppState>) -> Result<VerificationResult, String> {
     let config = state.config.lock().unwrap().clone();
     
-    let manifest_url = format!(
+    let manifest_url = manifest_url(&config);
+    let verifier = Verifier::new(config, manifest_url).map_err(|e| e.to_string())?;
+    
+    verifier.verify_game_files().await.map_err(|e| e.to_string())
+}
+
+// Re-downloads corrupted and missing game files from the mirrors. Refused
+// while a patch run is writing the same files.
+#[tauri::command]
+pub async fn repair_game_files(state: State<'_, AppState>) -> Result<RepairResult, String> {
+    let _game_files = state.game_files.try_lock()
+        .map_err(|_| "Patching or a repair is already running".to_string())?;
+    let config = state.config.lock().unwrap().clone();
+    
+    let manifest_url = manifest_url(&config);
+    let verifier = Verifier::new(config.clone(), manifest_url).map_err(|e| e.to_string())?;
+    // Its own control, so pausing or cancelling a patch run doesn't affect it.
+    let mut session = state.session.clone();
+    session.control = PatchControl::new();
+    let downloader = Downloader::with_session(config, session)
+        .map_err(|e| e.to_string())?;
+    
+    verifier.repair(&downloader).await.map_err(|e| e.to_string())
+}
+
+fn manifest_url(config: &Config) -> String {
+    format!(
         "{}/manifest.json",
         config.patcher.mirrors.first()
             .map(|m| m.url.as_str())
             .unwrap_or("https://patch.example.com")
-    );
-    
-    let verifier = Verifier::new(config, manifest_url).map_err(|e| e.to_string())?;
-    
-    verifier.verify_game_files().await.map_err(|e| e.to_string())
+    )
 }
 
 #[tauri::command]
@@ -347,7 +488,8 @@ pub async fn apply_game_settings(
     let game_dir = config.app.game_directory.as_ref()
         .ok_or("Game directory not set")?;
     
-    let manager = GameSettingsManager::new(game_dir);
+    let manager = GameSettingsManager::new(game_dir)
+        .with_wine_prefix(config.app.wine_prefix.as_ref());
     manager.apply_settings(&settings).map_err(|e| e.to_string())?;
     
     Ok(())
@@ -360,10 +502,118 @@ pub async fn load_game_settings(state: State<'_, AppState>) -> Result<GameSettin
     let game_dir = config.app.game_directory.as_ref()
         .ok_or("Game directory not set")?;
     
-    let manager = GameSettingsManager::new(game_dir);
+    let manager = GameSettingsManager::new(game_dir)
+        .with_wine_prefix(config.app.wine_prefix.as_ref());
     manager.load_settings().map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn load_client_lua(
+    state: State<'_, AppState>,
+    file: ClientLuaFile,
+) -> Result<LuaDocument, String> {
+    let config = state.config.lock().unwrap().clone();
+    
+    let game_dir = config.app.game_directory.as_ref()
+        .ok_or("Game directory not set")?;
+    
+    let manager = GameSettingsManager::new(game_dir);
+    manager.load_client_lua(file).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_client_lua(
+    state: State<'_, AppState>,
+    file: ClientLuaFile,
+    updates: Vec<(Vec<LuaKey>, LuaValue)>,
+) -> Result<(), String> {
+    let config = state.config.lock().unwrap().clone();
+    
+    let game_dir = config.app.game_directory.as_ref()
+        .ok_or("Game directory not set")?;
+    
+    let manager = GameSettingsManager::new(game_dir);
+    manager.update_client_lua(file, &updates).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn reset_client_lua(
+    state: State<'_, AppState>,
+    file: ClientLuaFile,
+) -> Result<Option<String>, String> {
+    let config = state.config.lock().unwrap().clone();
+    
+    let game_dir = config.app.game_directory.as_ref()
+        .ok_or("Game directory not set")?;
+    
+    let manager = GameSettingsManager::new(game_dir);
+    let backup = manager.reset_client_lua(file).map_err(|e| e.to_string())?;
+    
+    Ok(backup.map(|p| p.to_string_lossy().to_string()))
+}
+
+#[tauri::command]
+pub fn list_game_preset_profiles() -> Result<Vec<String>, String> {
+    GamePresetStore::list_profiles().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn list_game_presets(profile: Option<String>) -> Result<Vec<GamePreset>, String> {
+    let store = GamePresetStore::open_profile(profile.as_deref()).map_err(|e| e.to_string())?;
+    store.list().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn save_game_preset(
+    state: State<'_, AppState>,
+    name: String,
+    description: Option<String>,
+    profile: Option<String>,
+) -> Result<GamePreset, String> {
+    let config = state.config.lock().unwrap().clone();
+    
+    let game_dir = config.app.game_directory.as_ref()
+        .ok_or("Game directory not set")?;
+    
+    let manager = GameSettingsManager::new(game_dir)
+        .with_wine_prefix(config.app.wine_prefix.as_ref());
+    let store = GamePresetStore::open_profile(profile.as_deref()).map_err(|e| e.to_string())?;
+    store.save_current(&manager, &name, description).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn apply_game_preset(
+    state: State<'_, AppState>,
+    name: String,
+    profile: Option<String>,
+) -> Result<(), String> {
+    let config = state.config.lock().unwrap().clone();
+    
+    let game_dir = config.app.game_directory.as_ref()
+        .ok_or("Game directory not set")?;
+    
+    let manager = GameSettingsManager::new(game_dir)
+        .with_wine_prefix(config.app.wine_prefix.as_ref());
+    let store = GamePresetStore::open_profile(profile.as_deref()).map_err(|e| e.to_string())?;
+    store.apply(&manager, &name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_game_preset(name: String, profile: Option<String>) -> Result<bool, String> {
+    let store = GamePresetStore::open_profile(profile.as_deref()).map_err(|e| e.to_string())?;
+    store.delete(&name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn diff_game_presets(
+    left: String,
+    right: String,
+    profile: Option<String>,
+) -> Result<Vec<SettingDifference>, String> {
+    let store = GamePresetStore::open_profile(profile.as_deref()).map_err(|e| e.to_string())?;
+    store.diff(&left, &right).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn resolve_resource_path(app: AppHandle, path: String) -> Result<String, String> {
     let exe_dir = std::env::current_exe()
@@ -469,9 +719,52 @@ pub async fn manual_patch(state: State<'_, AppState>) -> Result<(), String> {
 #[tauri::command]
 pub async fn reset_cache(state: State<'_, AppState>) -> Result<(), String> {
     let config = state.config.lock().unwrap().clone();
-    let downloader = beam_core::Downloader::new(config).map_err(|e| e.to_string())?;
+    let downloader = Downloader::new(config).map_err(|e| e.to_string())?;
     
     downloader.clear_cache().map_err(|e| e.to_string())?;
     
     Ok(())
 }
+
+#[tauri::command]
+pub fn get_mirror_status(state: State<'_, AppState>) -> Result<Vec<MirrorStatus>, String> {
+    let config = state.config.lock().unwrap();
+    
+    Ok(state.session.mirror_health.snapshot(&config.patcher.mirrors))
+}
+
+#[tauri::command]
+pub async fn probe_mirrors(state: State<'_, AppState>) -> Result<Vec<MirrorStatus>, String> {
+    let config = state.config.lock().unwrap().clone();
+    let downloader = Downloader::with_session(config, state.session.clone())
+        .map_err(|e| e.to_string())?;
+    
+    Ok(downloader.probe_mirrors().await)
+}
+
+#[tauri::command]
+pub fn get_bandwidth_limits(state: State<'_, AppState>) -> Result<BandwidthConfig, String> {
+    Ok(state.session.rate_limiter.limits())
+}
+
+// Takes effect immediately, including for downloads already running.
+#[tauri::command]
+pub fn set_bandwidth_limits(
+    state: State<'_, AppState>,
+    limits: BandwidthConfig,
+) -> Result<(), String> {
+    state.session.rate_limiter.set_limits(limits.clone());
+    
+    let mut config = state.config.lock().unwrap();
+    config.patcher.bandwidth = limits;
+    
+    let exe_dir = std::env::current_exe()
+        .map_err(|e| format!("Failed to get executable path: {}", e))?
+        .parent()
+        .ok_or("Failed to get executable directory")?
+        .to_path_buf();
+    
+    config.save(exe_dir.join("config.yml")).map_err(|e| e.to_string())?;
+    
+    Ok(())
+}
@@ -2,6 +2,7 @@ mod commands;
 
 use anyhow::Result;
 use beam_core::Config;
+use beam_core::DownloadSession;
 use beam_core::Patcher;
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
@@ -14,12 +15,21 @@ pub struct PatchProgress {
     pub bytes_downloaded: u64,
     pub bytes_total: u64,
     pub status: String,
+    pub bytes_per_sec: u64,
+    pub eta_secs: Option<u64>,
+    // Download totals across the whole patch list.
+    pub overall_downloaded: u64,
+    pub overall_total: u64,
+    pub overall_eta_secs: Option<u64>,
 }
 
 pub struct AppState {
     pub config: Arc<Mutex<Config>>,
     pub patcher: Arc<Mutex<Option<Patcher>>>,
     pub progress: Arc<Mutex<PatchProgress>>,
+    pub session: DownloadSession,
+    // Held by commands that rewrite the game's GRFs or download patches.
+    pub game_files: Arc<tokio::sync::Mutex<()>>,
 }
 
 pub fn run_ui(config: Config) -> Result<()> {
@@ -33,7 +43,14 @@ pub fn run_ui(config: Config) -> Result<()> {
             bytes_downloaded: 0,
             bytes_total: 0,
             status: "Idle".to_string(),
+            bytes_per_sec: 0,
+            eta_secs: None,
+            overall_downloaded: 0,
+            overall_total: 0,
+            overall_eta_secs: None,
         })),
+        session: DownloadSession::new(&config),
+        game_files: Arc::new(tokio::sync::Mutex::new(())),
     };
     
     tauri::Builder::default()
@@ -41,6 +58,7 @@ pub fn run_ui(config: Config) -> Result<()> {
         .invoke_handler(tauri::generate_handler![
             commands::check_patches_available,
             commands::start_patching,
+            commands::apply_client_info,
             commands::check_updates,
             commands::perform_update,
             commands::get_login_url,
@@ -52,16 +70,35 @@ pub fn run_ui(config: Config) -> Result<()> {
             commands::get_server_status,
             commands::get_client_status,
             commands::verify_game_files,
+            commands::repair_game_files,
             commands::select_game_directory,
             commands::set_game_directory,
             commands::get_game_directory,
             commands::check_initial_setup,
             commands::apply_game_settings,
             commands::load_game_settings,
+            commands::load_client_lua,
+            commands::update_client_lua,
+            commands::reset_client_lua,
+            commands::list_game_preset_profiles,
+            commands::list_game_presets,
+            commands::save_game_preset,
+            commands::apply_game_preset,
+            commands::delete_game_preset,
+            commands::diff_game_presets,
             commands::resolve_resource_path,
             commands::open_setup,
             commands::manual_patch,
             commands::reset_cache,
+            commands::get_mirror_status,
+            commands::probe_mirrors,
+            commands::get_bandwidth_limits,
+            commands::set_bandwidth_limits,
+            commands::pause_patching,
+            commands::resume_patching,
+            commands::cancel_patching,
+            commands::plan_patches,
+            commands::get_change_log,
         ])
         .run(tauri::generate_context!("tauri.conf.json"))
         .expect("error while running tauri application");